@@ -1,10 +1,25 @@
-use std::{io::BufRead, ops::Range, path::PathBuf};
-
-use clap::{Args, Parser};
+use std::{
+    io::{self, BufRead, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, Parser, ValueEnum};
+use regex::Regex;
 use snafu::{ResultExt, Snafu};
 
+use output::{Format, Output};
+
+mod output;
+
 #[derive(Parser)]
-#[command(version, about)]
+#[command(
+    version,
+    about,
+    after_help = "FIELDS, BYTES and CHARS are lists of ranges separated by commas, counted \
+                  from 1: N, N-M, N- (from N to the end of the line) or -M (from the start of \
+                  the line to M)."
+)]
 struct Cli {
     /// Input files
     #[arg(default_value = "-")]
@@ -19,20 +34,140 @@ struct Cli {
         default_value_t = '\t'
     )]
     delimiter: char,
+    /// Split fields at every match of RE, implies --mode=plain
+    #[arg(
+        short = 'D',
+        long = "delimiter-regex",
+        value_name = "RE",
+        value_parser = parse_delimiter_regex,
+        conflicts_with_all = ["delimiter", "whitespace", "mode", "bytes", "chars"]
+    )]
+    delimiter_regex: Option<Regex>,
+    /// Split fields at runs of whitespace, ignoring any at the start or end of the line like
+    /// awk does, implies --mode=plain
+    #[arg(
+        short = 'w',
+        long = "whitespace",
+        conflicts_with_all = ["delimiter", "mode", "bytes", "chars"]
+    )]
+    whitespace: bool,
+    /// Select everything except the listed bytes, characters or fields
+    #[arg(long = "complement")]
+    complement: bool,
+    /// Join the selected fields with STRING instead of the input delimiter, or a space after
+    /// -D and -w. Also goes between the selected ranges of bytes or characters, which are
+    /// otherwise joined with nothing
+    #[arg(long = "output-delimiter", value_name = "STRING")]
+    output_delimiter: Option<String>,
+    /// Don't print lines that have no delimiter, which are otherwise printed whole
+    #[arg(
+        short = 's',
+        long = "only-delimited",
+        conflicts_with_all = ["bytes", "chars"]
+    )]
+    only_delimited: bool,
+    /// Write the selected fields as CSV, TSV or JSON. Records become JSON objects keyed by
+    /// the header row with --header or --field-names, and arrays otherwise
+    #[arg(
+        long = "output-format",
+        value_name = "FORMAT",
+        value_enum,
+        conflicts_with_all = ["bytes", "chars", "output_delimiter"]
+    )]
+    output_format: Option<Format>,
+    /// How the listed ranges are put together
+    #[arg(long = "order", value_enum, default_value_t = Order::Given)]
+    order: Order,
+    /// How lines are split into fields [default: csv, or plain for a multibyte delimiter]
+    #[arg(long = "mode", value_enum)]
+    mode: Option<Mode>,
+    /// Don't split multibyte characters: a character is selected by its last byte
+    #[arg(short = 'n', conflicts_with_all = ["chars", "fields", "field_names"])]
+    no_split: bool,
+    /// Treat the first record of each file as a header row, which is always printed
+    #[arg(long = "header", conflicts_with_all = ["bytes", "chars"])]
+    header: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Order {
+    /// Print ranges in the order they were listed, repeating positions listed more than once
+    Given,
+    /// Merge overlapping ranges and print them in input order, as POSIX cut does
+    Posix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Mode {
+    /// Read RFC 4180 CSV, where quoted fields may hold delimiters and newlines. The delimiter
+    /// must be a single byte
+    Csv,
+    /// Split each line at every delimiter, ignoring quotes
+    Plain,
 }
 
 #[derive(Args)]
 #[group(required = true, multiple = false)]
 struct RangeArgs {
     /// Select only these fields
-    #[arg(short = 'f', long = "fields", group = "range")]
+    #[arg(
+        short = 'f',
+        long = "fields",
+        group = "range",
+        allow_hyphen_values = true
+    )]
     fields: Option<String>,
     /// Select only these bytes
-    #[arg(short = 'b', long = "bytes", group = "range")]
+    #[arg(
+        short = 'b',
+        long = "bytes",
+        group = "range",
+        allow_hyphen_values = true
+    )]
     bytes: Option<String>,
     /// Select only these characters
-    #[arg(short = 'c', long = "chars", group = "range")]
+    #[arg(
+        short = 'c',
+        long = "chars",
+        group = "range",
+        allow_hyphen_values = true
+    )]
     chars: Option<String>,
+    /// Select only the fields with these names in the header row, implies --header
+    #[arg(
+        short = 'F',
+        long = "field-names",
+        value_name = "NAMES",
+        group = "range",
+        allow_hyphen_values = true
+    )]
+    field_names: Option<String>,
+}
+
+/// Where lines are split into fields in plain mode
+enum Splitter {
+    Literal(char),
+    Regex(Regex),
+    Whitespace,
+}
+
+impl Splitter {
+    fn split(&self, line: &str) -> Vec<String> {
+        match self {
+            Self::Literal(delimiter) => line.split(*delimiter).map(String::from).collect(),
+            Self::Regex(regex) => regex.split(line).map(String::from).collect(),
+            Self::Whitespace => line.split_whitespace().map(String::from).collect(),
+        }
+    }
+}
+
+/// What the positions count
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Bytes,
+    Chars,
+    Fields,
+    FieldNames,
 }
 
 #[derive(Debug, Snafu)]
@@ -57,65 +192,273 @@ pub enum CliError {
     InvalidStartEnd { start: usize, end: usize },
     #[snafu(display("{}: {}", path.display(), source))]
     Csv { source: csv::Error, path: PathBuf },
+    #[snafu(display(
+        "the delimiter '{}' isn't a single byte, which --mode=csv needs; try --mode=plain",
+        delimiter
+    ))]
+    CsvDelimiter { delimiter: char },
+    #[snafu(display("{}: no field named \"{}\" in the header row", path.display(), name))]
+    UnknownField { name: String, path: PathBuf },
+    #[snafu(display(
+        "{}: field \"{}\" must come before field \"{}\" in the header row",
+        path.display(),
+        start,
+        end
+    ))]
+    InvalidNameRange {
+        start: String,
+        end: String,
+        path: PathBuf,
+    },
+    #[snafu(display("{}", source))]
+    Regex { source: regex::Error },
+    #[snafu(display("{}", source))]
+    CsvOutput { source: csv::Error },
+    #[snafu(display("{}", source))]
+    Stdout { source: std::io::Error },
+    #[snafu(display("the delimiter regex \"{}\" matches the empty string", regex))]
+    EmptyDelimiterMatch { regex: String },
 }
 
 pub type CliResult<T> = Result<T, CliError>;
 
-pub type PositionList = Vec<Range<usize>>;
+/// A range of positions counted from 0, excluding `end`. Open-ended ranges (`3-`) have no end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    start: usize,
+    end: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PositionList {
+    positions: Vec<Position>,
+    /// Select everything outside the positions instead
+    complement: bool,
+    /// Merge the positions and select them in input order
+    merge: bool,
+}
+
+impl PositionList {
+    /// Returns the ranges to select from a line of `len` bytes, chars or fields
+    fn resolve(&self, len: usize) -> Vec<Range<usize>> {
+        let clamp = |pos: &Position| pos.start.min(len)..pos.end.unwrap_or(len).min(len);
+        if !self.complement && !self.merge {
+            return self
+                .positions
+                .iter()
+                .map(clamp)
+                .filter(|range| !range.is_empty())
+                .collect();
+        }
+
+        let mut selected = vec![false; len];
+        for range in self.positions.iter().map(clamp) {
+            selected[range].fill(true);
+        }
+        let mut ranges: Vec<Range<usize>> = vec![];
+        for (i, _) in selected
+            .iter()
+            .enumerate()
+            .filter(|(_, selected)| **selected != self.complement)
+        {
+            match ranges.last_mut() {
+                Some(range) if range.end == i => range.end += 1,
+                _ => ranges.push(i..i + 1),
+            }
+        }
+        ranges
+    }
+}
+
+fn parse_num(s: &str, ranges: &str) -> CliResult<usize> {
+    if s.trim().starts_with("+") {
+        return Err(CliError::InvalidPosition {
+            text: ranges.into(),
+        });
+    }
+    let n: usize = s.parse().context(PositionParseSnafu {
+        text: ranges.to_string(),
+    })?;
+    if n == 0 {
+        return Err(CliError::InvalidPosition {
+            text: ranges.into(),
+        });
+    }
+    Ok(n)
+}
 
-// TODO: refactor
+/// Parses a list like `1,3-5,7-` of positions counted from 1. A range missing its start
+/// (`-5`) begins at the first position, and one missing its end (`3-`) runs to the end of the line.
 fn parse_pos(ranges: &str) -> CliResult<PositionList> {
-    let mut positions: PositionList = vec![];
+    let mut positions = vec![];
     for range in ranges.split(',') {
-        let mut nums: Vec<usize> = vec![];
-        for s in range.split('-') {
-            if s.trim().starts_with("+") {
-                return Err(CliError::InvalidPosition {
-                    text: ranges.into(),
-                });
+        let position = match range.split_once('-') {
+            None => {
+                let n = parse_num(range, ranges)?;
+                Position {
+                    start: n - 1,
+                    end: Some(n),
+                }
             }
-            let n: usize = s.parse().context(PositionParseSnafu {
-                text: ranges.to_string(),
-            })?;
-            if n == 0 {
+            Some(("", "")) => {
                 return Err(CliError::InvalidPosition {
                     text: ranges.into(),
                 });
             }
-            nums.push(n);
-        }
-        if nums.len() > 2 {
-            return Err(CliError::InvalidPosition {
-                text: ranges.into(),
+            Some((start, end)) => {
+                let start = if start.is_empty() {
+                    1
+                } else {
+                    parse_num(start, ranges)?
+                };
+                let end = if end.is_empty() {
+                    None
+                } else {
+                    Some(parse_num(end, ranges)?)
+                };
+                if let Some(end) = end
+                    && start >= end
+                {
+                    return Err(CliError::InvalidStartEnd { start, end });
+                }
+                Position {
+                    start: start - 1,
+                    end,
+                }
+            }
+        };
+        positions.push(position);
+    }
+
+    Ok(PositionList {
+        positions,
+        complement: false,
+        merge: false,
+    })
+}
+
+/// Resolves a list like `title,year-` of names from the `header` row. Names in a range are
+/// split at the first hyphen that leaves a known name (or nothing) on both sides.
+fn parse_names(names: &str, header: &[String], path: &Path) -> CliResult<PositionList> {
+    let index = |name: &str| header.iter().position(|field| field == name);
+    let mut positions = vec![];
+    for name in names.split(',') {
+        if let Some(i) = index(name) {
+            positions.push(Position {
+                start: i,
+                end: Some(i + 1),
             });
+            continue;
         }
-        if nums.len() == 2 && nums[0] >= nums[1] {
-            return Err(CliError::InvalidStartEnd {
-                start: nums[0],
-                end: nums[1],
+        let range = name.match_indices('-').find_map(|(i, _)| {
+            let (start, end) = (&name[..i], &name[i + 1..]);
+            let start_index = if start.is_empty() { 0 } else { index(start)? };
+            let end_index = if end.is_empty() {
+                None
+            } else {
+                Some(index(end)? + 1)
+            };
+            Some((start, end, start_index, end_index))
+        });
+        let Some((start, end, start_index, end_index)) = range else {
+            return Err(CliError::UnknownField {
+                name: name.to_string(),
+                path: path.to_path_buf(),
+            });
+        };
+        if end_index.is_some_and(|end_index| start_index >= end_index) {
+            return Err(CliError::InvalidNameRange {
+                start: start.to_string(),
+                end: end.to_string(),
+                path: path.to_path_buf(),
             });
         }
-        let is_range = nums.len() == 2;
-        if nums.len() == 1 {
-            nums.push(nums[0] - 1);
-            nums.reverse();
-        }
+        positions.push(Position {
+            start: start_index,
+            end: end_index,
+        });
+    }
 
-        if is_range {
-            nums[0] -= 1;
-        }
+    Ok(PositionList {
+        positions,
+        ..Default::default()
+    })
+}
 
-        positions.push(Range {
-            start: nums[0],
-            end: nums[1],
-        });
+fn parse_delimiter_regex(text: &str) -> CliResult<Regex> {
+    let regex = Regex::new(text).context(RegexSnafu)?;
+    if regex.is_match("") {
+        return Err(CliError::EmptyDelimiterMatch { regex: text.into() });
     }
+    Ok(regex)
+}
 
-    Ok(positions)
+/// Moves `i` back to the start of the character it's in, so that with -n a character is
+/// selected by its last byte
+fn floor_char_boundary(bytes: &[u8], mut i: usize) -> usize {
+    while i > 0 && i < bytes.len() && bytes[i] & 0b1100_0000 == 0b1000_0000 {
+        i -= 1;
+    }
+    i
+}
+
+/// Selects fields from a record. With the `width` of the header row, positions are resolved
+/// against the header instead, and the fields a short record is missing are `None`.
+fn select_fields<'a>(
+    fields: &'a [String],
+    pos_list: &PositionList,
+    width: Option<usize>,
+) -> Vec<Option<&'a str>> {
+    pos_list
+        .resolve(width.unwrap_or(fields.len()))
+        .into_iter()
+        .flat_map(|range| range.map(|i| fields.get(i).map(String::as_str)))
+        .collect()
 }
 
 pub fn run() -> CliResult<()> {
     let cli = Cli::parse();
+    let (ranges, kind) = if let Some(ref bytes) = cli.ranges.bytes {
+        (bytes, Kind::Bytes)
+    } else if let Some(ref chars) = cli.ranges.chars {
+        (chars, Kind::Chars)
+    } else if let Some(ref fields) = cli.ranges.fields {
+        (fields, Kind::Fields)
+    } else if let Some(ref names) = cli.ranges.field_names {
+        (names, Kind::FieldNames)
+    } else {
+        unreachable!("clap requires one of --bytes, --chars, --fields or --field-names")
+    };
+    let configure = |pos_list: PositionList| PositionList {
+        complement: cli.complement,
+        merge: cli.order == Order::Posix,
+        ..pos_list
+    };
+    let mut pos_list = match kind {
+        // Names are resolved from the header row of each file
+        Kind::FieldNames => PositionList::default(),
+        _ => configure(parse_pos(ranges)?),
+    };
+    let (splitter, delimiter) = if let Some(regex) = cli.delimiter_regex {
+        (Splitter::Regex(regex), " ".to_string())
+    } else if cli.whitespace {
+        (Splitter::Whitespace, " ".to_string())
+    } else {
+        (Splitter::Literal(cli.delimiter), cli.delimiter.to_string())
+    };
+    let mode = match (cli.mode, &splitter) {
+        (Some(mode), _) => mode,
+        (None, Splitter::Literal(delimiter)) if delimiter.is_ascii() => Mode::Csv,
+        (None, _) => Mode::Plain,
+    };
+    if mode == Mode::Csv && !cli.delimiter.is_ascii() {
+        return Err(CliError::CsvDelimiter {
+            delimiter: cli.delimiter,
+        });
+    }
+    let range_delimiter = cli.output_delimiter.as_deref().unwrap_or("");
+    let field_delimiter = cli.output_delimiter.as_deref().unwrap_or(&delimiter);
+    let mut output = Output::new(cli.output_format, field_delimiter);
 
     for path in cli.files {
         let buffer = match utils::reader_from_path(path.clone()) {
@@ -125,52 +468,105 @@ pub fn run() -> CliResult<()> {
                 continue;
             }
         };
-        if let Some(ref byte_ranges) = cli.ranges.bytes {
-            let pos_list = parse_pos(byte_ranges)?;
-            for line in buffer.lines() {
-                let line = line.context(IoPathSnafu { path: path.clone() })?;
-                for pos in pos_list.iter().cloned() {
-                    print!("{}", String::from_utf8_lossy(&line.as_bytes()[pos]));
+        match kind {
+            Kind::Bytes => {
+                let mut stdout = io::stdout().lock();
+                for line in buffer.split(b'\n') {
+                    let mut line = line.context(IoPathSnafu { path: path.clone() })?;
+                    if line.last() == Some(&b'\r') {
+                        line.pop();
+                    }
+                    // The bytes are written as they are, even if a range splits a character
+                    let mut selected = vec![];
+                    for (i, range) in pos_list.resolve(line.len()).into_iter().enumerate() {
+                        if i > 0 {
+                            selected.extend_from_slice(range_delimiter.as_bytes());
+                        }
+                        let range = if cli.no_split {
+                            floor_char_boundary(&line, range.start)
+                                ..floor_char_boundary(&line, range.end)
+                        } else {
+                            range
+                        };
+                        selected.extend_from_slice(&line[range]);
+                    }
+                    selected.push(b'\n');
+                    stdout.write_all(&selected).context(StdoutSnafu)?;
                 }
-                println!()
             }
-        } else if let Some(ref char_ranges) = cli.ranges.chars {
-            let pos_list = parse_pos(char_ranges)?;
-            for line in buffer.lines() {
-                let line = line.context(IoPathSnafu { path: path.clone() })?;
-                for pos in pos_list.iter().cloned() {
-                    print!(
-                        "{}",
-                        line.chars()
-                            .skip(pos.start)
-                            .take(pos.end - pos.start)
-                            .collect::<String>()
-                    );
+            Kind::Chars => {
+                for line in buffer.lines() {
+                    let line = line.context(IoPathSnafu { path: path.clone() })?;
+                    let chars: Vec<char> = line.chars().collect();
+                    let selected: Vec<String> = pos_list
+                        .resolve(chars.len())
+                        .into_iter()
+                        .map(|range| chars[range].iter().collect())
+                        .collect();
+                    println!("{}", selected.join(range_delimiter));
                 }
-                println!()
             }
-        } else if let Some(ref fields) = cli.ranges.fields {
-            let pos_list = parse_pos(fields)?;
-            let mut csv_reader = csv::ReaderBuilder::new()
-                .has_headers(false)
-                .delimiter(cli.delimiter as u8)
-                .from_reader(buffer);
-            for result in csv_reader.records() {
-                let record = result.context(CsvSnafu { path: path.clone() })?;
-                let fields: Vec<&str> = record.iter().collect();
-                for pos in pos_list.iter().cloned() {
-                    print!("{}", fields[pos].join(&cli.delimiter.to_string()));
+            Kind::Fields | Kind::FieldNames => {
+                let mut records: Box<dyn Iterator<Item = CliResult<Vec<String>>>> = match mode {
+                    Mode::Csv => {
+                        let csv_reader = csv::ReaderBuilder::new()
+                            .has_headers(false)
+                            .flexible(true)
+                            .delimiter(cli.delimiter as u8)
+                            .from_reader(buffer);
+                        let path = path.clone();
+                        Box::new(csv_reader.into_records().map(move |record| {
+                            record
+                                .map(|record| record.iter().map(String::from).collect())
+                                .context(CsvSnafu { path: path.clone() })
+                        }))
+                    }
+                    Mode::Plain => {
+                        let path = path.clone();
+                        let splitter = &splitter;
+                        Box::new(buffer.lines().map(move |line| {
+                            line.map(|line| splitter.split(&line))
+                                .context(IoPathSnafu { path: path.clone() })
+                        }))
+                    }
+                };
+                // Records of structured output line up with the header row
+                let mut width = None;
+                if (cli.header || kind == Kind::FieldNames)
+                    && let Some(result) = records.next()
+                {
+                    let header = result?;
+                    if kind == Kind::FieldNames {
+                        pos_list = configure(parse_names(ranges, &header, &path)?);
+                    }
+                    if !matches!(output, Output::Text { .. }) {
+                        width = Some(header.len());
+                    }
+                    output.write_header(&select_fields(&header, &pos_list, None))?;
+                }
+                for result in records {
+                    let record = result?;
+                    // -w leaves no fields at all on a blank line
+                    if record.len() <= 1 {
+                        if cli.only_delimited {
+                            continue;
+                        }
+                        if let Output::Text { .. } = output {
+                            println!("{}", record.first().map_or("", String::as_str));
+                            continue;
+                        }
+                    }
+                    output.write(&select_fields(&record, &pos_list, width))?;
                 }
-                println!()
             }
         }
     }
-    Ok(())
+    output.finish()
 }
 
 #[cfg(test)]
 mod unit_tests {
-    use super::parse_pos;
+    use super::{Path, parse_names, parse_pos};
 
     #[test]
     fn test_parse_pos() {
@@ -226,9 +622,6 @@ mod unit_tests {
         let res = parse_pos("1,");
         assert!(res.is_err());
 
-        let res = parse_pos("1-");
-        assert!(res.is_err());
-
         let res = parse_pos("1-1-1");
         assert!(res.is_err());
 
@@ -253,35 +646,100 @@ mod unit_tests {
         // All the following are acceptable
         let res = parse_pos("1");
         assert!(res.is_ok());
-        assert_eq!(res.unwrap(), vec![0..1]);
+        assert_eq!(res.unwrap().resolve(20), vec![0..1]);
 
         let res = parse_pos("01");
         assert!(res.is_ok());
-        assert_eq!(res.unwrap(), vec![0..1]);
+        assert_eq!(res.unwrap().resolve(20), vec![0..1]);
 
         let res = parse_pos("1,3");
         assert!(res.is_ok());
 
-        assert_eq!(res.unwrap(), vec![0..1, 2..3]);
+        assert_eq!(res.unwrap().resolve(20), vec![0..1, 2..3]);
 
         let res = parse_pos("001,0003");
         assert!(res.is_ok());
-        assert_eq!(res.unwrap(), vec![0..1, 2..3]);
+        assert_eq!(res.unwrap().resolve(20), vec![0..1, 2..3]);
 
         let res = parse_pos("1-3");
         assert!(res.is_ok());
-        assert_eq!(res.unwrap(), vec![0..3]);
+        assert_eq!(res.unwrap().resolve(20), vec![0..3]);
 
         let res = parse_pos("0001-03");
         assert!(res.is_ok());
-        assert_eq!(res.unwrap(), vec![0..3]);
+        assert_eq!(res.unwrap().resolve(20), vec![0..3]);
 
         let res = parse_pos("1,7,3-5");
         assert!(res.is_ok());
-        assert_eq!(res.unwrap(), vec![0..1, 6..7, 2..5]);
+        assert_eq!(res.unwrap().resolve(20), vec![0..1, 6..7, 2..5]);
 
         let res = parse_pos("15,19-20");
         assert!(res.is_ok());
-        assert_eq!(res.unwrap(), vec![14..15, 18..20]);
+        assert_eq!(res.unwrap().resolve(20), vec![14..15, 18..20]);
+
+        // Open-ended ranges
+        let res = parse_pos("3-");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap().resolve(20), vec![2..20]);
+
+        let res = parse_pos("-5");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap().resolve(20), vec![0..5]);
+
+        let res = parse_pos("-5-");
+        assert!(res.is_err());
+
+        let res = parse_pos("1,-");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        // Ranges are cut short at the end of the line
+        let pos_list = parse_pos("2,4-,8-9").unwrap();
+        assert_eq!(pos_list.resolve(10), vec![1..2, 3..10, 7..9]);
+        assert_eq!(pos_list.resolve(5), vec![1..2, 3..5]);
+        assert!(pos_list.resolve(1).is_empty());
+
+        let mut pos_list = parse_pos("5-6,2").unwrap();
+        pos_list.complement = true;
+        assert_eq!(pos_list.resolve(8), vec![0..1, 2..4, 6..8]);
+        assert_eq!(pos_list.resolve(3), vec![0..1, 2..3]);
+
+        let mut pos_list = parse_pos("1-").unwrap();
+        pos_list.complement = true;
+        assert!(pos_list.resolve(8).is_empty());
+
+        let mut pos_list = parse_pos("5-6,2,1,4-5").unwrap();
+        pos_list.merge = true;
+        assert_eq!(pos_list.resolve(8), vec![0..2, 3..6]);
+        pos_list.complement = true;
+        assert_eq!(pos_list.resolve(8), vec![2..3, 6..8]);
+    }
+
+    #[test]
+    fn test_parse_names() {
+        let header = ["id", "title", "year", "release-date", "rating"].map(String::from);
+        let path = Path::new("movies.csv");
+        let names = |list| parse_names(list, &header, path).map(|pos_list| pos_list.resolve(5));
+
+        assert_eq!(names("year,id").unwrap(), vec![2..3, 0..1]);
+        assert_eq!(names("title-year").unwrap(), vec![1..3]);
+        assert_eq!(names("year-").unwrap(), vec![2..5]);
+        assert_eq!(names("-title").unwrap(), vec![0..2]);
+        // Hyphens in names are kept when they don't split a range
+        assert_eq!(names("release-date").unwrap(), vec![3..4]);
+        assert_eq!(names("title-release-date").unwrap(), vec![1..4]);
+
+        assert_eq!(
+            names("name").unwrap_err().to_string(),
+            "movies.csv: no field named \"name\" in the header row"
+        );
+        assert!(names("title-name").is_err());
+        assert!(names("").is_err());
+        assert_eq!(
+            names("year-title").unwrap_err().to_string(),
+            "movies.csv: field \"year\" must come before field \"title\" in the header row"
+        );
     }
 }
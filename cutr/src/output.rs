@@ -0,0 +1,136 @@
+use std::io::{self, Stdout};
+
+use clap::ValueEnum;
+use snafu::ResultExt;
+
+use crate::{CliResult, CsvOutputSnafu};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Comma-separated values, quoted where needed
+    Csv,
+    /// Tab-separated values, quoted where needed
+    Tsv,
+    /// A JSON array holding a record per line
+    Json,
+    /// A JSON record per line
+    Jsonl,
+}
+
+/// Where the selected fields of each record are written
+pub enum Output {
+    /// The fields joined with a delimiter, as they were read
+    Text {
+        delimiter: String,
+    },
+    Delimited(Box<csv::Writer<Stdout>>),
+    Json {
+        /// The selected names from the header row, which turn records into objects
+        keys: Option<Vec<String>>,
+        /// One record per line without the enclosing array
+        lines: bool,
+        written: usize,
+    },
+}
+
+impl Output {
+    pub fn new(format: Option<Format>, delimiter: &str) -> Self {
+        let delimited = |delimiter| {
+            csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .flexible(true)
+                .from_writer(io::stdout())
+        };
+        match format {
+            None => Self::Text {
+                delimiter: delimiter.to_string(),
+            },
+            Some(Format::Csv) => Self::Delimited(Box::new(delimited(b','))),
+            Some(Format::Tsv) => Self::Delimited(Box::new(delimited(b'\t'))),
+            Some(format) => Self::Json {
+                keys: None,
+                lines: format == Format::Jsonl,
+                written: 0,
+            },
+        }
+    }
+
+    /// Writes the selected fields of a header row, which become the keys of JSON records
+    pub fn write_header(&mut self, fields: &[Option<&str>]) -> CliResult<()> {
+        if let Self::Json { keys, .. } = self {
+            *keys = Some(
+                fields
+                    .iter()
+                    .map(|field| field.unwrap_or_default().to_string())
+                    .collect(),
+            );
+            return Ok(());
+        }
+        self.write(fields)
+    }
+
+    /// Writes the selected fields of a record. Missing fields are empty, or `null` in JSON.
+    pub fn write(&mut self, fields: &[Option<&str>]) -> CliResult<()> {
+        match self {
+            Self::Text { delimiter } => {
+                let fields: Vec<&str> = fields
+                    .iter()
+                    .map(|field| field.unwrap_or_default())
+                    .collect();
+                println!("{}", fields.join(delimiter))
+            }
+            Self::Delimited(writer) => writer
+                .write_record(fields.iter().map(|field| field.unwrap_or_default()))
+                .context(CsvOutputSnafu)?,
+            Self::Json {
+                keys,
+                lines,
+                written,
+            } => {
+                let record = match keys {
+                    Some(keys) => {
+                        let pairs: Vec<String> = keys
+                            .iter()
+                            .zip(fields)
+                            .map(|(key, field)| {
+                                format!("{}:{}", json_string(Some(key)), json_string(*field))
+                            })
+                            .collect();
+                        format!("{{{}}}", pairs.join(","))
+                    }
+                    None => {
+                        let values: Vec<String> =
+                            fields.iter().map(|field| json_string(*field)).collect();
+                        format!("[{}]", values.join(","))
+                    }
+                };
+                match (*lines, *written) {
+                    (true, _) => println!("{record}"),
+                    (false, 0) => print!("[\n{record}"),
+                    (false, _) => print!(",\n{record}"),
+                }
+                *written += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes the JSON array and flushes any buffered records
+    pub fn finish(&mut self) -> CliResult<()> {
+        match self {
+            Self::Text { .. } => {}
+            Self::Delimited(writer) => writer
+                .flush()
+                .map_err(csv::Error::from)
+                .context(CsvOutputSnafu)?,
+            Self::Json { lines: true, .. } => {}
+            Self::Json { written: 0, .. } => println!("[]"),
+            Self::Json { .. } => println!("\n]"),
+        }
+        Ok(())
+    }
+}
+
+fn json_string(text: Option<&str>) -> String {
+    serde_json::Value::from(text).to_string()
+}
@@ -9,6 +9,7 @@ const PRG: &str = "cutr";
 const CSV: &str = "tests/inputs/movies1.csv";
 const TSV: &str = "tests/inputs/movies1.tsv";
 const BOOKS: &str = "tests/inputs/books.tsv";
+const ALIGNED: &str = "tests/inputs/aligned.txt";
 
 // --------------------------------------------------
 fn random_string() -> String {
@@ -60,7 +61,7 @@ fn dies_not_enough_args() -> Result<()> {
     dies(
         &[CSV],
         "the following required arguments were not provided:\n  \
-        <--fields <FIELDS>|--bytes <BYTES>|--chars <CHARS>>",
+        <--fields <FIELDS>|--bytes <BYTES>|--chars <CHARS>|--field-names <NAMES>>",
     )
 }
 
@@ -164,14 +165,12 @@ fn run(args: &[&str], expected_file: &str) -> Result<()> {
 }
 
 // --------------------------------------------------
-fn run_lossy(args: &[&str], expected_file: &str) -> Result<()> {
-    let contents = fs::read(expected_file)?;
-    let expected = String::from_utf8_lossy(&contents);
+/// Compares the raw bytes of the output, which may split multibyte characters
+fn run_bytes(args: &[&str], expected_file: &str) -> Result<()> {
+    let expected = fs::read(expected_file)?;
     let output = Command::cargo_bin(PRG)?.args(args).output().expect("fail");
     assert!(output.status.success());
-
-    let stdout = String::from_utf8(output.stdout).expect("invalid UTF-8");
-    assert_eq!(stdout, expected);
+    assert_eq!(output.stdout, expected);
     Ok(())
 }
 
@@ -280,7 +279,7 @@ fn tsv_b2() -> Result<()> {
 // --------------------------------------------------
 #[test]
 fn tsv_b8() -> Result<()> {
-    run_lossy(&[TSV, "-b", "8"], "tests/expected/movies1.tsv.b8.out")
+    run_bytes(&[TSV, "-b", "8"], "tests/expected/movies1.tsv.b8.out")
 }
 
 // --------------------------------------------------
@@ -298,7 +297,7 @@ fn tsv_b2_3() -> Result<()> {
 // --------------------------------------------------
 #[test]
 fn tsv_b1_8() -> Result<()> {
-    run_lossy(&[TSV, "-b", "1-8"], "tests/expected/movies1.tsv.b1-8.out")
+    run_bytes(&[TSV, "-b", "1-8"], "tests/expected/movies1.tsv.b1-8.out")
 }
 
 // --------------------------------------------------
@@ -342,3 +341,321 @@ fn tsv_c1_8() -> Result<()> {
 fn repeated_value() -> Result<()> {
     run(&[BOOKS, "-c", "1,1"], "tests/expected/books.c1,1.out")
 }
+
+// --------------------------------------------------
+#[test]
+fn tsv_f2_open() -> Result<()> {
+    run(&[TSV, "-f", "2-"], "tests/expected/movies1.tsv.f2-.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_f_open_2() -> Result<()> {
+    run(&[TSV, "-f", "-2"], "tests/expected/movies1.tsv.f-2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_f1_3_list() -> Result<()> {
+    run(&[TSV, "-f", "1,3"], "tests/expected/movies1.tsv.f1,3.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_f2_complement() -> Result<()> {
+    run(
+        &[TSV, "-f", "2", "--complement"],
+        "tests/expected/movies1.tsv.f2.complement.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_c5_open() -> Result<()> {
+    run(&[TSV, "-c", "5-"], "tests/expected/movies1.tsv.c5-.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_c1_4_complement() -> Result<()> {
+    run(
+        &[TSV, "-c", "1-4", "--complement"],
+        "tests/expected/movies1.tsv.c5-.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_b_complement_open() -> Result<()> {
+    run(
+        &[TSV, "-b", "-4", "--complement"],
+        "tests/expected/movies1.tsv.c5-.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn undelimited_f2() -> Result<()> {
+    run(
+        &["tests/inputs/undelimited.tsv", "-f", "2"],
+        "tests/expected/undelimited.tsv.f2.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn undelimited_f2_only_delimited() -> Result<()> {
+    run(
+        &["tests/inputs/undelimited.tsv", "-f", "2", "-s"],
+        "tests/expected/undelimited.tsv.f2.s.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_only_delimited_chars() -> Result<()> {
+    dies(
+        &[TSV, "-c", "1", "-s"],
+        "the argument '--chars <CHARS>' cannot be used with '--only-delimited'",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_f1_2_output_delimiter() -> Result<()> {
+    run(
+        &[TSV, "-f", "1-2", "--output-delimiter", " | "],
+        "tests/expected/movies1.tsv.f1-2.odelim.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_c1_2_5_output_delimiter() -> Result<()> {
+    run(
+        &[TSV, "-c", "1-2,5", "--output-delimiter", ":"],
+        "tests/expected/movies1.tsv.c1-2,5.odelim.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_f_given_order() -> Result<()> {
+    run(
+        &[TSV, "-f", "3,1-2,2"],
+        "tests/expected/movies1.tsv.f3,1-2,2.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_f_posix_order() -> Result<()> {
+    run(&[TSV, "-f", "3,1-2,2", "--order", "posix"], TSV)
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_field_names() -> Result<()> {
+    let expected = "tests/expected/movies1.csv.Ftitle,year.out";
+    run(&[CSV, "-d", ",", "-F", "title,year"], expected)?;
+    run(&[CSV, "-d", ",", "-F", "title-year"], expected)?;
+    run(&[CSV, "-d", ",", "-F", "-year"], expected)?;
+    run(
+        &[CSV, "-d", ",", "-F", "director", "--complement"],
+        expected,
+    )?;
+    // The same names pick other columns from a file in another order
+    run(
+        &["tests/inputs/movies3.csv", "-d", ",", "-F", "title,year"],
+        expected,
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_header() -> Result<()> {
+    run(
+        &["tests/inputs/undelimited.tsv", "-f", "2", "-s", "--header"],
+        "tests/expected/undelimited.tsv.f2.s.out",
+    )?;
+    run(
+        &["tests/inputs/undelimited.tsv", "-F", "count", "-s"],
+        "tests/expected/undelimited.tsv.f2.s.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_unknown_field_name() -> Result<()> {
+    dies(
+        &[CSV, "-d", ",", "-F", "title,rating"],
+        r#"tests/inputs/movies1.csv: no field named "rating" in the header row"#,
+    )?;
+    dies(
+        &[CSV, "-d", ",", "-F", "year-title"],
+        r#"field "year" must come before field "title" in the header row"#,
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_f5_out_of_range() -> Result<()> {
+    run(&[TSV, "-f", "5"], "tests/expected/movies1.tsv.f5.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_b30_40_out_of_range() -> Result<()> {
+    run(
+        &[TSV, "-b", "30-40"],
+        "tests/expected/movies1.tsv.b30-40.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn books_b1_no_split() -> Result<()> {
+    // Without -n, half of a character is written as its raw byte, like GNU cut
+    run_bytes(&[BOOKS, "-b", "1"], "tests/expected/books.tsv.b1.out")?;
+    run(
+        &[BOOKS, "-b", "1", "-n"],
+        "tests/expected/books.tsv.b1.n.out",
+    )?;
+    run(
+        &[BOOKS, "-b", "1-2", "-n"],
+        "tests/expected/books.tsv.b1-2.n.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_no_split_chars() -> Result<()> {
+    dies(
+        &[BOOKS, "-c", "1", "-n"],
+        "the argument '--chars <CHARS>' cannot be used with '-n'",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn books_csv_mode() -> Result<()> {
+    let books = "tests/inputs/books.csv";
+    run(
+        &[books, "-d", ",", "-f", "3"],
+        "tests/expected/books.csv.f3.out",
+    )?;
+    run(
+        &[books, "-d", ",", "-f", "3", "--mode", "csv"],
+        "tests/expected/books.csv.f3.out",
+    )?;
+    run(
+        &[books, "-d", ",", "-f", "3", "--mode", "plain"],
+        "tests/expected/books.csv.f3.plain.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_csv_mode_multibyte_delimiter() -> Result<()> {
+    dies(
+        &[TSV, "-d", "é", "-f", "2", "--mode", "csv"],
+        "the delimiter 'é' isn't a single byte, which --mode=csv needs; try --mode=plain",
+    )?;
+    run(
+        &[TSV, "-d", "é", "-f", "2", "--mode", "plain"],
+        "tests/expected/movies1.tsv.f2.dmultibyte.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_multibyte_delimiter() -> Result<()> {
+    run(
+        &[TSV, "-d", "é", "-f", "2"],
+        "tests/expected/movies1.tsv.f2.dmultibyte.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn aligned_whitespace() -> Result<()> {
+    run(
+        &[ALIGNED, "-w", "-f", "4,1"],
+        "tests/expected/aligned.txt.f4,1.w.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn aligned_delimiter_regex() -> Result<()> {
+    run(
+        &[ALIGNED, "-D", " +", "-f", "2-3", "--output-delimiter", ","],
+        "tests/expected/aligned.txt.f2-3.D.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_delimiter_regex() -> Result<()> {
+    dies(
+        &[ALIGNED, "-D", " *", "-f", "1"],
+        "the delimiter regex \" *\" matches the empty string",
+    )?;
+    dies(
+        &[ALIGNED, "-D", "(", "-f", "1"],
+        "invalid value '(' for '--delimiter-regex <RE>'",
+    )?;
+    dies(
+        &[ALIGNED, "-w", "-b", "1"],
+        "the argument '--whitespace' cannot be used with '--bytes <BYTES>'",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn books_output_formats() -> Result<()> {
+    let books = "tests/inputs/books.csv";
+    for format in ["csv", "tsv", "json", "jsonl"] {
+        run(
+            &[books, "-d", ",", "-f", "3,1", "--output-format", format],
+            &format!("tests/expected/books.csv.f3,1.{format}.out"),
+        )?;
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_header_json() -> Result<()> {
+    run(
+        &[TSV, "--header", "-f", "1-2", "--output-format", "json"],
+        "tests/expected/movies1.tsv.f1-2.header.json.out",
+    )?;
+    run(
+        &[TSV, "-F", "title-year", "--output-format", "json"],
+        "tests/expected/movies1.tsv.f1-2.header.json.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn short_record_output_formats() -> Result<()> {
+    // The second record has no year, which stays under its own key
+    let short = "tests/inputs/short.csv";
+    for format in ["csv", "tsv", "json", "jsonl"] {
+        run(
+            &[short, "-d", ",", "-F", "year,id", "--output-format", format],
+            &format!("tests/expected/short.csv.Fyear,id.{format}.out"),
+        )?;
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_output_format_bytes() -> Result<()> {
+    dies(
+        &[TSV, "-b", "1", "--output-format", "csv"],
+        "the argument '--bytes <BYTES>' cannot be used with '--output-format <FORMAT>'",
+    )
+}
@@ -0,0 +1,301 @@
+use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, BufRead, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileTypeExt, MetadataExt},
+    },
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{CliError, CliResult, report, walk::Entry};
+use chrono::{DateTime, Local};
+
+/// Upper bound on the paths passed to a single `-exec ... +` invocation
+const MAX_BATCH_SIZE: usize = 4096;
+
+/// An `-exec`, `-execdir` or `-ok` command
+pub struct Exec {
+    command: Vec<String>,
+    /// Paths are appended to the command in batches (`{} +`)
+    batch: bool,
+    /// The command runs from the entry's directory (`-execdir`)
+    in_dir: bool,
+    /// The user is asked before running the command (`-ok`)
+    pub prompt: bool,
+    pending: Vec<OsString>,
+    pending_dir: Option<PathBuf>,
+    /// A batch that already ran failed
+    failed: bool,
+}
+
+impl Exec {
+    pub fn new(mut command: Vec<String>, in_dir: bool, prompt: bool) -> CliResult<Self> {
+        let batch = command.len() >= 2
+            && command[command.len() - 1] == "+"
+            && command[command.len() - 2] == "{}";
+        if batch {
+            command.truncate(command.len() - 2);
+        }
+        if command.is_empty() {
+            return Err(CliError::MissingCommand);
+        }
+        if batch && prompt {
+            return Err(CliError::BatchedPrompt);
+        }
+
+        Ok(Self {
+            command,
+            batch,
+            in_dir,
+            prompt,
+            pending: vec![],
+            pending_dir: None,
+            failed: false,
+        })
+    }
+
+    /// Runs the command on `path`, or queues it for a batch.
+    /// Returns whether the command succeeded, which batches always do as in find: their
+    /// failures are only reported by `finish`.
+    pub fn run(&mut self, path: &Path) -> bool {
+        let (dir, arg) = self.split_path(path);
+        if !self.batch {
+            let args: Vec<OsString> = self.command[1..]
+                .iter()
+                .map(|part| replace_braces(part, &arg))
+                .collect();
+            if self.prompt && !confirm(&self.command[0], &args) {
+                return false;
+            }
+            return self.spawn(&args, dir.as_deref());
+        }
+
+        if self.pending.len() >= MAX_BATCH_SIZE || (self.in_dir && self.pending_dir != dir) {
+            self.failed |= !self.flush();
+        }
+        self.pending_dir = dir;
+        self.pending.push(arg);
+
+        true
+    }
+
+    /// Runs the command on any queued paths, and returns whether every batch succeeded
+    pub fn finish(&mut self) -> bool {
+        self.flush() && !self.failed
+    }
+
+    fn flush(&mut self) -> bool {
+        if self.pending.is_empty() {
+            return true;
+        }
+        let mut args: Vec<OsString> = self.command[1..].iter().map(OsString::from).collect();
+        args.append(&mut self.pending);
+        let dir = self.pending_dir.take();
+
+        self.spawn(&args, dir.as_deref())
+    }
+
+    /// Returns the directory to run the command from, and the argument that stands for `path`
+    fn split_path(&self, path: &Path) -> (Option<PathBuf>, OsString) {
+        if !self.in_dir {
+            return (None, path.as_os_str().to_owned());
+        }
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let mut arg = OsString::from("./");
+        arg.push(path.file_name().unwrap_or(path.as_os_str()));
+
+        (Some(dir), arg)
+    }
+
+    fn spawn(&self, args: &[OsString], dir: Option<&Path>) -> bool {
+        let mut command = Command::new(&self.command[0]);
+        command.args(args);
+        if let Some(dir) = dir {
+            command.current_dir(dir);
+        }
+        if self.prompt {
+            command.stdin(Stdio::null());
+        }
+        match command.status() {
+            Ok(status) => status.success(),
+            Err(err) => {
+                report::error(Path::new(&self.command[0]), err);
+                false
+            }
+        }
+    }
+}
+
+fn replace_braces(part: &str, path: &OsStr) -> OsString {
+    let mut arg = OsString::new();
+    for (i, piece) in part.split("{}").enumerate() {
+        if i > 0 {
+            arg.push(path);
+        }
+        arg.push(piece);
+    }
+    arg
+}
+
+fn confirm(program: &str, args: &[OsString]) -> bool {
+    let args: Vec<_> = args.iter().map(|arg| arg.to_string_lossy()).collect();
+    eprint!("< {} {} > ? ", program, args.join(" "));
+    let mut answer = String::new();
+    match io::stdin().lock().read_line(&mut answer) {
+        Ok(_) => answer.trim_start().starts_with(['y', 'Y']),
+        Err(_) => false,
+    }
+}
+
+/// Removes the entry, which must have been visited after its contents
+pub fn delete(entry: &Entry) -> io::Result<()> {
+    // Like find, refuse to delete the current directory
+    if entry.path() == Path::new(".") {
+        return Ok(());
+    }
+    if entry.file_type().is_dir() {
+        fs::remove_dir(entry.path())
+    } else {
+        fs::remove_file(entry.path())
+    }
+}
+
+fn type_char(entry: &Entry) -> char {
+    let file_type = entry.file_type();
+    if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else if file_type.is_char_device() {
+        'c'
+    } else if file_type.is_block_device() {
+        'b'
+    } else {
+        'f'
+    }
+}
+
+/// Expands a `-printf` format for the entry, keeping its path as raw bytes
+pub fn format(format: &str, entry: &Entry) -> Vec<u8> {
+    let path = entry.path().as_os_str().as_bytes();
+    let metadata = entry.metadata().ok();
+    let mut output = vec![];
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => output.push(b'\n'),
+                Some('t') => output.push(b'\t'),
+                Some('0') => output.push(b'\0'),
+                Some('\\') => output.push(b'\\'),
+                Some(other) => {
+                    output.push(b'\\');
+                    push_char(&mut output, other);
+                }
+                None => output.push(b'\\'),
+            },
+            '%' => match chars.next() {
+                Some('%') => output.push(b'%'),
+                Some('p') => output.extend_from_slice(path),
+                Some('f') => {
+                    output.extend_from_slice(path.rsplit(|b| *b == b'/').next().unwrap_or(path))
+                }
+                Some('h') => match path.iter().rposition(|b| *b == b'/') {
+                    Some(i) => output.extend_from_slice(&path[..i]),
+                    None => output.push(b'.'),
+                },
+                Some('y') => push_char(&mut output, type_char(entry)),
+                Some(directive @ ('s' | 'm' | 'u' | 't')) => {
+                    let Some(ref metadata) = metadata else {
+                        continue;
+                    };
+                    let text = match directive {
+                        's' => metadata.size().to_string(),
+                        'm' => format!("{:o}", metadata.mode() & 0o7777),
+                        'u' => users::get_user_by_uid(metadata.uid())
+                            .map(|user| user.name().to_string_lossy().into_owned())
+                            .unwrap_or_else(|| metadata.uid().to_string()),
+                        _ => {
+                            DateTime::from_timestamp(metadata.mtime(), metadata.mtime_nsec() as u32)
+                                .map(|date| {
+                                    date.with_timezone(&Local)
+                                        .format("%a %b %e %H:%M:%S %Y")
+                                        .to_string()
+                                })
+                                .unwrap_or_default()
+                        }
+                    };
+                    output.extend_from_slice(text.as_bytes());
+                }
+                Some(other) => {
+                    output.push(b'%');
+                    push_char(&mut output, other);
+                }
+                None => output.push(b'%'),
+            },
+            _ => push_char(&mut output, c),
+        }
+    }
+    output
+}
+
+fn push_char(output: &mut Vec<u8>, c: char) {
+    output.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+/// Writes `bytes` to stdout as they are, without a trailing newline
+pub fn print_raw(bytes: &[u8]) {
+    let mut stdout = io::stdout().lock();
+    let _ = stdout.write_all(bytes);
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_exec_new() {
+        let exec = Exec::new(vec!["echo".into(), "{}".into(), "+".into()], false, false).unwrap();
+        assert!(exec.batch);
+        assert_eq!(exec.command, ["echo"]);
+
+        let exec = Exec::new(vec!["echo".into(), "{}".into()], false, false).unwrap();
+        assert!(!exec.batch);
+
+        assert!(Exec::new(vec![], false, false).is_err());
+        assert!(Exec::new(vec!["{}".into(), "+".into()], false, false).is_err());
+        assert!(Exec::new(vec!["rm".into(), "{}".into(), "+".into()], false, true).is_err());
+    }
+
+    #[test]
+    fn test_replace_braces() {
+        let path = OsStr::new("a/b.txt");
+        assert_eq!(replace_braces("{}", path), "a/b.txt");
+        assert_eq!(replace_braces("--file={}.bak", path), "--file=a/b.txt.bak");
+        assert_eq!(replace_braces("{}{}", path), "a/b.txta/b.txt");
+        assert_eq!(replace_braces("plain", path), "plain");
+    }
+
+    #[test]
+    fn test_split_path() {
+        let exec = Exec::new(vec!["ls".into()], true, false).unwrap();
+        assert_eq!(
+            exec.split_path(Path::new("a/b/c.txt")),
+            (Some(PathBuf::from("a/b")), OsString::from("./c.txt"))
+        );
+        assert_eq!(
+            exec.split_path(Path::new("c.txt")),
+            (Some(PathBuf::from(".")), OsString::from("./c.txt"))
+        );
+    }
+}
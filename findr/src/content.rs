@@ -0,0 +1,152 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+    str::FromStr,
+};
+
+use infer::MatcherType;
+use regex::bytes::Regex;
+
+use crate::glob;
+
+/// How many bytes are read to classify a file
+const SNIFF_SIZE: u64 = 8192;
+
+/// Returns whether a line of the file matches `regex`, reading no further than the first match.
+/// Lines are matched without their `\n` or `\r\n`, so that `$` matches at their end.
+pub fn contains(path: &Path, regex: &Regex) -> io::Result<bool> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut line = vec![];
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(false);
+        }
+        let text = line.strip_suffix(b"\n").unwrap_or(&line);
+        let text = text.strip_suffix(b"\r").unwrap_or(text);
+        if regex.is_match(text) {
+            return Ok(true);
+        }
+    }
+}
+
+/// What a file's magic bytes say it is
+#[derive(Debug, PartialEq, Eq)]
+pub struct FileKind {
+    /// text, binary, executable, archive, audio, book, document, font, image or video
+    pub kind: &'static str,
+    pub mime_type: &'static str,
+    /// The usual extension of recognized formats, such as `png` or `elf`
+    pub extension: Option<&'static str>,
+}
+
+impl FileKind {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        if let Some(found) = infer::get(bytes) {
+            let kind = match found.matcher_type() {
+                MatcherType::App => "executable",
+                MatcherType::Archive => "archive",
+                MatcherType::Audio => "audio",
+                MatcherType::Book => "book",
+                MatcherType::Doc => "document",
+                MatcherType::Font => "font",
+                MatcherType::Image => "image",
+                MatcherType::Text | MatcherType::Custom => "text",
+                MatcherType::Video => "video",
+            };
+            return Self {
+                kind,
+                mime_type: found.mime_type(),
+                extension: Some(found.extension()),
+            };
+        }
+        if is_text(bytes) {
+            Self {
+                kind: "text",
+                mime_type: "text/plain",
+                extension: None,
+            }
+        } else {
+            Self {
+                kind: "binary",
+                mime_type: "application/octet-stream",
+                extension: None,
+            }
+        }
+    }
+
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        let mut bytes = vec![];
+        File::open(path)?.take(SNIFF_SIZE).read_to_end(&mut bytes)?;
+        Ok(Self::from_bytes(&bytes))
+    }
+}
+
+/// Text has no NUL bytes and is valid UTF-8, except maybe for a character cut off at the end
+fn is_text(bytes: &[u8]) -> bool {
+    if bytes.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    }
+}
+
+/// A `--mime` argument: a shell pattern for the kind, MIME type or extension of a file
+#[derive(Debug, Clone)]
+pub struct MimeTest(Regex);
+
+impl MimeTest {
+    pub fn matches(&self, kind: &FileKind) -> bool {
+        [Some(kind.kind), Some(kind.mime_type), kind.extension]
+            .into_iter()
+            .flatten()
+            .any(|text| self.0.is_match(text.as_bytes()))
+    }
+}
+
+impl FromStr for MimeTest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        glob::parse_iglob(s).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_file_kind() {
+        let png = FileKind::from_bytes(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+        assert_eq!(png.kind, "image");
+        assert_eq!(png.mime_type, "image/png");
+        assert_eq!(png.extension, Some("png"));
+
+        let mut header = b"\x7fELF\x02\x01\x01".to_vec();
+        header.resize(64, 0);
+        let elf = FileKind::from_bytes(&header);
+        assert_eq!(elf.kind, "executable");
+        assert_eq!(elf.extension, Some("elf"));
+
+        assert_eq!(FileKind::from_bytes(b"hello\n").kind, "text");
+        assert_eq!(FileKind::from_bytes(b"").kind, "text");
+        // A multibyte character cut off by the end of the sniffed bytes
+        assert_eq!(FileKind::from_bytes(b"caf\xc3").kind, "text");
+        assert_eq!(FileKind::from_bytes(b"caf\xe9 ").kind, "binary");
+        assert_eq!(FileKind::from_bytes(b"a\0b").kind, "binary");
+    }
+
+    #[test]
+    fn test_mime_test() {
+        let png = FileKind::from_bytes(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+        assert!("image".parse::<MimeTest>().unwrap().matches(&png));
+        assert!("image/*".parse::<MimeTest>().unwrap().matches(&png));
+        assert!("PNG".parse::<MimeTest>().unwrap().matches(&png));
+        assert!(!"text".parse::<MimeTest>().unwrap().matches(&png));
+        assert!(!"image/jpeg".parse::<MimeTest>().unwrap().matches(&png));
+    }
+}
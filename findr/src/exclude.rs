@@ -0,0 +1,119 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::report;
+use ignore::{
+    Match,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+
+/// Ignore files read from each directory, from lowest to highest precedence
+const IGNORE_FILES: [&str; 3] = [".git/info/exclude", ".gitignore", ".ignore"];
+
+/// Decides which entries a walk leaves out because they are hidden or ignored by version control
+pub struct Exclusions {
+    vcs: bool,
+    hidden: bool,
+    current_dir: PathBuf,
+    /// Rules from the ignore files of each directory seen so far, keyed by absolute path
+    rules: HashMap<PathBuf, Option<Gitignore>>,
+    /// Whether each directory seen so far is excluded
+    excluded_dirs: HashMap<PathBuf, bool>,
+}
+
+impl Exclusions {
+    pub fn new(vcs: bool, hidden: bool) -> Self {
+        Self {
+            vcs,
+            hidden,
+            current_dir: env::current_dir().unwrap_or_default(),
+            rules: HashMap::new(),
+            excluded_dirs: HashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.vcs || self.hidden
+    }
+
+    /// Returns whether the entry at `path`, `depth` levels below a search path, is excluded.
+    /// Search paths themselves are never excluded.
+    pub fn is_excluded(&mut self, path: &Path, is_dir: bool, depth: usize) -> bool {
+        if depth == 0 || !self.is_enabled() {
+            return false;
+        }
+        let path = self.current_dir.join(path);
+        self.is_excluded_absolute(&path, is_dir, depth)
+    }
+
+    fn is_excluded_absolute(&mut self, path: &Path, is_dir: bool, depth: usize) -> bool {
+        if depth == 0 {
+            return false;
+        }
+        if is_dir && let Some(excluded) = self.excluded_dirs.get(path) {
+            return *excluded;
+        }
+        let name = path.file_name().unwrap_or_default();
+        let excluded = (self.hidden && name.as_encoded_bytes().starts_with(b"."))
+            || (self.vcs && name == ".git")
+            // Nothing inside an excluded directory can be re-included, even by a negated pattern
+            || path
+                .parent()
+                .is_some_and(|parent| self.is_excluded_absolute(parent, true, depth - 1))
+            || (self.vcs && self.is_ignored(path, is_dir));
+        if is_dir {
+            self.excluded_dirs.insert(path.to_path_buf(), excluded);
+        }
+        excluded
+    }
+
+    /// Checks `path` against the ignore files of its ancestors, the closest one first,
+    /// up to the root of the repository containing it
+    fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        for dir in path.ancestors().skip(1) {
+            let rules = self
+                .rules
+                .entry(dir.to_path_buf())
+                .or_insert_with(|| read_rules(dir));
+            if let Some(rules) = rules {
+                match rules.matched(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+            if dir.join(".git").exists() {
+                break;
+            }
+        }
+        false
+    }
+}
+
+fn read_rules(dir: &Path) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut found = false;
+    for file in IGNORE_FILES {
+        let path = dir.join(file);
+        if !path.is_file() {
+            continue;
+        }
+        found = true;
+        if let Some(err) = builder.add(&path) {
+            report::error(&path, err);
+        }
+    }
+    if !found {
+        return None;
+    }
+    match builder.build() {
+        Ok(rules) => Some(rules),
+        Err(err) => {
+            report::error(dir, err);
+            None
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use regex::bytes::{Regex, RegexBuilder};
+
+/// The character classes of bracket expressions, as in `[[:digit:]]`
+const CLASSES: [&str; 12] = [
+    "alnum", "alpha", "blank", "cntrl", "digit", "graph", "lower", "print", "punct", "space",
+    "upper", "xdigit",
+];
+
+/// Translates a shell glob into an anchored regex with fnmatch semantics:
+/// `*` and `?` also match `/` and leading dots, `[...]` is a bracket expression
+/// negated by `!` or `^` that may hold classes like `[:digit:]`, and a backslash
+/// escapes the next character.
+/// Wildcards also match bytes that aren't valid UTF-8.
+fn glob_to_regex(glob: &str, case_insensitive: bool) -> Result<Regex, String> {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str("(?-u:.)*"),
+            '?' => pattern.push_str("(?:.|(?-u:.))"),
+            '\\' => match chars.next() {
+                Some(escaped) => pattern.push_str(&regex::escape(&escaped.to_string())),
+                None => pattern.push_str(r"\\"),
+            },
+            '[' => {
+                let mut class = String::from("[");
+                let mut rest = chars.clone();
+                if rest.next_if(|c| *c == '!' || *c == '^').is_some() {
+                    class.push('^');
+                }
+                // A `]` right after the opening bracket is a literal
+                if rest.next_if_eq(&']').is_some() {
+                    class.push_str(r"\]");
+                }
+                let mut closed = false;
+                while let Some(c) = rest.next() {
+                    match c {
+                        ']' => {
+                            closed = true;
+                            break;
+                        }
+                        // Character classes like [:digit:] are kept for the regex
+                        '[' if rest.peek() == Some(&':') => {
+                            let ahead: String = rest.clone().collect();
+                            match ahead[1..].split_once(":]") {
+                                Some((name, _)) if CLASSES.contains(&name) => {
+                                    class.push_str(&format!("[:{name}:]"));
+                                    rest.nth(name.len() + 2);
+                                }
+                                _ => class.push_str(r"\["),
+                            }
+                        }
+                        '\\' | '[' | '&' | '~' => {
+                            class.push('\\');
+                            class.push(c);
+                        }
+                        _ => class.push(c),
+                    }
+                }
+                if closed {
+                    class.push(']');
+                    pattern.push_str(&class);
+                    chars = rest;
+                } else {
+                    // Like fnmatch, an unclosed bracket is a literal
+                    pattern.push_str(r"\[");
+                }
+            }
+            _ => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+
+    RegexBuilder::new(&pattern)
+        .case_insensitive(case_insensitive)
+        .dot_matches_new_line(true)
+        .build()
+        .map_err(|err| format!("invalid glob \"{glob}\": {err}"))
+}
+
+pub fn parse_glob(glob: &str) -> Result<Regex, String> {
+    glob_to_regex(glob, false)
+}
+
+pub fn parse_iglob(glob: &str) -> Result<Regex, String> {
+    glob_to_regex(glob, true)
+}
+
+/// Anchors a regex to the whole path, as find's `-regex` does
+pub fn parse_path_regex(regex: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{regex})$"))
+}
+
+pub fn parse_path_iregex(regex: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(&format!("^(?:{regex})$"))
+        .case_insensitive(true)
+        .build()
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_glob() {
+        let glob = parse_glob("*.csv").unwrap();
+        assert!(glob.is_match(b"b.csv"));
+        assert!(glob.is_match(b".csv"));
+        assert!(!glob.is_match(b"acsvb"));
+        assert!(!glob.is_match(b"b.CSV"));
+        assert!(parse_iglob("*.csv").unwrap().is_match(b"b.CSV"));
+
+        let glob = parse_glob("?.t[xs]?").unwrap();
+        assert!(glob.is_match(b"a.txt"));
+        assert!(glob.is_match(b"d.tsv"));
+        assert!(!glob.is_match(b"ab.txt"));
+
+        let glob = parse_glob("[!a-c]*").unwrap();
+        assert!(glob.is_match(b"d.txt"));
+        assert!(!glob.is_match(b"b.csv"));
+        assert!(parse_glob("[^a]").unwrap().is_match(b"b"));
+
+        let glob = parse_glob("[]x]").unwrap();
+        assert!(glob.is_match(b"]"));
+        assert!(glob.is_match(b"x"));
+
+        assert!(parse_glob(r"\*").unwrap().is_match(b"*"));
+        assert!(!parse_glob(r"\*").unwrap().is_match(b"a"));
+        assert!(parse_glob("[a").unwrap().is_match(b"[a"));
+        assert!(parse_glob("a+(b)").unwrap().is_match(b"a+(b)"));
+        assert!(parse_glob("[[]").unwrap().is_match(b"["));
+
+        let glob = parse_glob("?[[:digit:]]").unwrap();
+        assert!(glob.is_match(b"a1"));
+        assert!(!glob.is_match(b"ab"));
+        let glob = parse_glob("[![:alpha:]_]*").unwrap();
+        assert!(glob.is_match(b"1.txt"));
+        assert!(!glob.is_match(b"_x"));
+        assert!(!glob.is_match(b"x"));
+        assert!(parse_glob("[[:x]").unwrap().is_match(b":"));
+        assert!(parse_glob("[[:nope:]]").unwrap().is_match(b"[]"));
+
+        // `*` crosses directory separators, as in find's -path
+        assert!(
+            parse_glob("*/b/*.csv")
+                .unwrap()
+                .is_match(b"tests/a/b/b.csv")
+        );
+    }
+
+    #[test]
+    fn test_glob_non_utf8() {
+        assert!(parse_glob("*.txt").unwrap().is_match(b"caf\xe9.txt"));
+        assert!(parse_glob("caf?.txt").unwrap().is_match(b"caf\xe9.txt"));
+        assert!(
+            parse_glob("caf?.txt")
+                .unwrap()
+                .is_match("café.txt".as_bytes())
+        );
+        assert!(
+            !parse_glob("caf?.txt")
+                .unwrap()
+                .is_match(b"caf\xc3\xa9\xe9.txt")
+        );
+    }
+
+    #[test]
+    fn test_path_regex() {
+        let regex = parse_path_regex(r".*\.csv").unwrap();
+        assert!(regex.is_match(b"a/b.csv"));
+        assert!(!regex.is_match(b"a/b.csv.bak"));
+        assert!(!parse_path_regex("csv").unwrap().is_match(b"a/b.csv"));
+        assert!(parse_path_regex("a|b").unwrap().is_match(b"b"));
+        assert!(!parse_path_regex("a|b").unwrap().is_match(b"ab"));
+        assert!(parse_path_iregex(".*CSV").unwrap().is_match(b"a/b.csv"));
+        assert!(parse_path_regex("(").is_err());
+    }
+}
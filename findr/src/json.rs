@@ -0,0 +1,155 @@
+use std::{
+    fs,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileTypeExt, MetadataExt},
+    },
+    path::Path,
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{report, walk::Entry};
+
+/// A field of the `--json` objects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Field {
+    Path,
+    Name,
+    Type,
+    Size,
+    Mode,
+    Uid,
+    Gid,
+    Mtime,
+    Depth,
+    Target,
+}
+
+/// The object printed for a match. Fields that weren't asked for are left out,
+/// and `target` is null for entries that aren't symbolic links.
+#[derive(Debug, Default, Serialize)]
+struct Record<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<Data<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<Data<'a>>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    file_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    /// Permission bits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mode: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gid: Option<u32>,
+    /// Seconds since the epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mtime: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<Option<Data<'a>>>,
+}
+
+/// A path as a string if it's valid UTF-8, or else as `{"bytes": <base64>}`, so that none of
+/// its bytes are lost
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Data<'a> {
+    Text(&'a str),
+    Bytes { bytes: String },
+}
+
+impl<'a> Data<'a> {
+    pub fn new(path: &'a Path) -> Self {
+        let bytes = path.as_os_str().as_bytes();
+        match str::from_utf8(bytes) {
+            Ok(text) => Self::Text(text),
+            Err(_) => Self::Bytes {
+                bytes: utils::base64(bytes),
+            },
+        }
+    }
+}
+
+fn type_name(entry: &Entry) -> &'static str {
+    let file_type = entry.file_type();
+    if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_fifo() {
+        "fifo"
+    } else if file_type.is_socket() {
+        "socket"
+    } else if file_type.is_char_device() {
+        "char_device"
+    } else if file_type.is_block_device() {
+        "block_device"
+    } else {
+        "file"
+    }
+}
+
+/// Serializes the chosen `fields` of the entry, only reading the metadata if one of them needs it
+pub fn format(entry: &Entry, fields: &[Field]) -> String {
+    let has = |field| fields.contains(&field);
+    let target = has(Field::Target).then(|| fs::read_link(entry.path()).ok());
+    let mut record = Record::default();
+    if has(Field::Path) {
+        record.path = Some(Data::new(entry.path()));
+    }
+    if has(Field::Name) {
+        record.name = Some(Data::new(Path::new(entry.file_name())));
+    }
+    if has(Field::Type) {
+        record.file_type = Some(type_name(entry));
+    }
+    if has(Field::Depth) {
+        record.depth = Some(entry.depth());
+    }
+    let stat_fields = [
+        Field::Size,
+        Field::Mode,
+        Field::Uid,
+        Field::Gid,
+        Field::Mtime,
+    ];
+    if stat_fields.into_iter().any(has) {
+        match entry.metadata() {
+            Ok(metadata) => {
+                record.size = has(Field::Size).then(|| metadata.size());
+                record.mode = has(Field::Mode).then(|| metadata.mode() & 0o7777);
+                record.uid = has(Field::Uid).then(|| metadata.uid());
+                record.gid = has(Field::Gid).then(|| metadata.gid());
+                record.mtime = has(Field::Mtime).then(|| metadata.mtime());
+            }
+            Err(err) => report::error(entry.path(), err),
+        }
+    }
+    record.target = target
+        .as_ref()
+        .map(|target| target.as_deref().map(Data::new));
+
+    // A record of strings and numbers always serializes
+    serde_json::to_string(&record).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_data() {
+        let json = |bytes: &[u8]| {
+            serde_json::to_string(&Data::new(Path::new(OsStr::from_bytes(bytes)))).unwrap()
+        };
+        assert_eq!(json(b"caf\xc3\xa9"), r#""café""#);
+        assert_eq!(json(b"caf\xe9"), r#"{"bytes":"Y2Fm6Q=="}"#);
+    }
+}
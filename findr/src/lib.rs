@@ -1,13 +1,30 @@
-use clap::{Parser, ValueEnum};
-use regex::Regex;
-use relative_path::RelativePath;
-use snafu::Snafu;
+use actions::Exec;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use exclude::Exclusions;
+use predicates::{NumTest, PermTest, Predicate, SizeTest, TimeField};
+use regex::bytes::Regex;
+use snafu::{ResultExt, Snafu};
 use std::{
-    io,
-    path::{MAIN_SEPARATOR, PathBuf},
+    ffi::OsString,
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+    sync::mpsc,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
 };
+use walk::{Entry, WalkOptions};
 use walkdir::WalkDir;
 
+mod actions;
+mod content;
+mod exclude;
+mod glob;
+mod json;
+mod predicates;
+mod report;
+mod walk;
+
 #[derive(Debug, Snafu)]
 pub enum CliError {
     Io {
@@ -18,14 +35,14 @@ pub enum CliError {
         path: PathBuf,
         source: io::Error,
     },
-    #[snafu(display("Failed to decode path: {:?}", path))]
-    PathDecode {
-        path: PathBuf,
-    },
     WalkDir {
         source: walkdir::Error,
         path: PathBuf,
     },
+    #[snafu(display("missing command for --exec, --execdir or --ok"))]
+    MissingCommand,
+    #[snafu(display("--ok doesn't support the batched \"{{}} +\" form"))]
+    BatchedPrompt,
 }
 
 pub type CliResult<T = ()> = Result<T, CliError>;
@@ -43,77 +60,511 @@ enum EntryType {
 #[derive(Parser)]
 #[command(version, about)]
 struct Cli {
-    // Search paths
+    /// Search paths
     paths: Vec<PathBuf>,
-    // Types
+    /// Never follow symbolic links (the default)
+    #[arg(short = 'P', overrides_with_all = ["follow_links", "follow_root_links"])]
+    no_follow_links: bool,
+    /// Follow symbolic links, reporting links that point back to an ancestor
+    #[arg(short = 'L', overrides_with_all = ["no_follow_links", "follow_root_links"])]
+    follow_links: bool,
+    /// Only follow symbolic links given as search paths
+    #[arg(short = 'H', overrides_with_all = ["no_follow_links", "follow_links"])]
+    follow_root_links: bool,
+    /// Types
     #[arg(short = 't', long = "type", value_enum, value_name = "TYPE")]
     types: Vec<EntryType>,
-    // Names
-    #[arg(short = 'n', long = "name", value_name = "NAME")]
+    /// Base name matches the shell pattern PATTERN
+    #[arg(short = 'n', long = "name", value_name = "PATTERN", value_parser = glob::parse_glob)]
     names: Vec<Regex>,
+    /// Like --name, but case insensitive
+    #[arg(long = "iname", value_name = "PATTERN", value_parser = glob::parse_iglob)]
+    inames: Vec<Regex>,
+    /// Base name contains a match for REGEX (the behaviour of --name before it took patterns)
+    #[arg(long = "name-regex", value_name = "REGEX")]
+    name_regexes: Vec<Regex>,
+    /// Path matches the shell pattern PATTERN, where * and ? also match /
+    #[arg(long = "path", value_name = "PATTERN", value_parser = glob::parse_glob)]
+    path_globs: Vec<Regex>,
+    /// Like --path, but case insensitive
+    #[arg(long = "ipath", value_name = "PATTERN", value_parser = glob::parse_iglob)]
+    ipath_globs: Vec<Regex>,
+    /// Whole path matches REGEX
+    #[arg(long = "regex", value_name = "REGEX", value_parser = glob::parse_path_regex)]
+    regexes: Vec<Regex>,
+    /// Like --regex, but case insensitive
+    #[arg(long = "iregex", value_name = "REGEX", value_parser = glob::parse_path_iregex)]
+    iregexes: Vec<Regex>,
+    /// File size is more (+N), less (-N) or exactly N units of c (bytes), w (2 bytes),
+    /// b (512 bytes, the default), k, M or G
+    #[arg(
+        long = "size",
+        value_name = "[+-]N[cwbkMG]",
+        allow_hyphen_values = true
+    )]
+    sizes: Vec<SizeTest>,
+    /// File was last modified more (+N), less (-N) or exactly N days ago
+    #[arg(long = "mtime", value_name = "[+-]N", allow_hyphen_values = true)]
+    mtimes: Vec<NumTest>,
+    /// File was last modified more (+N), less (-N) or exactly N minutes ago
+    #[arg(long = "mmin", value_name = "[+-]N", allow_hyphen_values = true)]
+    mmins: Vec<NumTest>,
+    /// File was last accessed more (+N), less (-N) or exactly N days ago
+    #[arg(long = "atime", value_name = "[+-]N", allow_hyphen_values = true)]
+    atimes: Vec<NumTest>,
+    /// File status was last changed more (+N), less (-N) or exactly N days ago
+    #[arg(long = "ctime", value_name = "[+-]N", allow_hyphen_values = true)]
+    ctimes: Vec<NumTest>,
+    /// File was modified more recently than FILE
+    #[arg(long = "newer", value_name = "FILE")]
+    newer: Option<PathBuf>,
+    /// File is an empty regular file or directory
+    #[arg(long = "empty")]
+    empty: bool,
+    /// Permission bits are exactly MODE, all of -MODE or any of /MODE,
+    /// in octal or symbolic notation
+    #[arg(long = "perm", value_name = "MODE", allow_hyphen_values = true)]
+    perm: Option<PermTest>,
+    /// File is owned by USER (name or ID)
+    #[arg(long = "user", value_name = "USER", value_parser = predicates::parse_user)]
+    user: Option<u32>,
+    /// File belongs to GROUP (name or ID)
+    #[arg(long = "group", value_name = "GROUP", value_parser = predicates::parse_group)]
+    group: Option<u32>,
+    /// Regular file has a line that matches REGEX. Reading stops at the first match
+    #[arg(long = "contains", value_name = "REGEX")]
+    contains: Vec<Regex>,
+    /// Regular file's magic bytes match PATTERN, a shell pattern for its kind (text, binary,
+    /// executable, archive, audio, book, document, font, image or video), its MIME type
+    /// (image/png) or its usual extension (elf, zip)
+    #[arg(long = "mime", value_name = "PATTERN")]
+    mimes: Vec<content::MimeTest>,
+    /// Descend at most LEVELS levels below the search paths
+    #[arg(long = "maxdepth", value_name = "LEVELS")]
+    max_depth: Option<usize>,
+    /// Don't apply any tests at levels less than LEVELS
+    #[arg(long = "mindepth", value_name = "LEVELS")]
+    min_depth: Option<usize>,
+    /// Don't descend into matching directories
+    #[arg(long = "prune")]
+    prune: bool,
+    /// Don't descend into directories on other filesystems
+    #[arg(long = "xdev")]
+    xdev: bool,
+    /// Process a directory's contents before the directory itself
+    #[arg(long = "depth")]
+    depth: bool,
+    /// Print the path followed by a newline (the default action)
+    #[arg(long = "print")]
+    print: bool,
+    /// Print the path followed by a NUL character
+    #[arg(long = "print0")]
+    print0: bool,
+    /// Print FORMAT, expanding \n, \t, \0 and the directives %p (path), %f (name),
+    /// %h (parent directory), %s (size), %m (octal mode), %u (owner), %t (modification time)
+    /// and %y (type)
+    #[arg(long = "printf", value_name = "FORMAT")]
+    printf: Option<String>,
+    /// Run COMMAND on each match, with {} replaced by its path. End COMMAND with ";",
+    /// or with "{} +" to pass many paths to each run
+    #[arg(
+        long = "exec",
+        value_name = "COMMAND",
+        num_args = 1..,
+        allow_hyphen_values = true,
+        value_terminator = ";"
+    )]
+    exec: Vec<String>,
+    /// Like --exec, but run COMMAND from the directory containing the match
+    #[arg(
+        long = "execdir",
+        value_name = "COMMAND",
+        num_args = 1..,
+        allow_hyphen_values = true,
+        value_terminator = ";"
+    )]
+    execdir: Vec<String>,
+    /// Like --exec, but ask for confirmation on stdin first
+    #[arg(
+        long = "ok",
+        value_name = "COMMAND",
+        num_args = 1..,
+        allow_hyphen_values = true,
+        value_terminator = ";"
+    )]
+    ok: Vec<String>,
+    /// Skip entries excluded by .gitignore, .ignore and .git/info/exclude files, and .git
+    /// directories
+    #[arg(long = "ignore-vcs", overrides_with = "no_ignore")]
+    ignore_vcs: bool,
+    /// Don't skip ignored entries (undoes --ignore-vcs)
+    #[arg(long = "no-ignore", overrides_with = "ignore_vcs")]
+    no_ignore: bool,
+    /// Skip hidden entries, whose names start with a dot
+    #[arg(long = "no-hidden")]
+    no_hidden: bool,
+    /// Print each match as a JSON object on its own line, and errors as JSON objects on stderr.
+    /// Paths that aren't valid UTF-8 are written as {"bytes": <base64>} instead of a string.
+    #[arg(long = "json")]
+    json: bool,
+    /// Comma-separated fields of the --json objects (all of them by default). Leaving out
+    /// size, mode, uid, gid and mtime saves reading each match's metadata
+    #[arg(
+        long = "json-fields",
+        value_name = "FIELDS",
+        value_enum,
+        value_delimiter = ',',
+        requires = "json"
+    )]
+    json_fields: Vec<json::Field>,
+    /// Walk directories on N threads, or one per CPU if N is 0. Matches are output in no
+    /// particular order unless N is 1 or --sort is given. --depth, --delete and --ok always
+    /// walk on a single thread
+    #[arg(long = "threads", value_name = "N", default_value_t = 0)]
+    threads: usize,
+    /// Output matches sorted by path
+    #[arg(long = "sort")]
+    sort: bool,
+    /// Delete matches, implies --depth
+    #[arg(long = "delete")]
+    delete: bool,
 }
 
-pub fn run() -> CliResult {
-    let cli = Cli::parse();
+impl Cli {
+    fn predicates(&self) -> CliResult<Vec<Predicate>> {
+        let mut predicates: Vec<Predicate> =
+            self.sizes.iter().copied().map(Predicate::Size).collect();
+        let times = [
+            (&self.mtimes, TimeField::Modified, false),
+            (&self.mmins, TimeField::Modified, true),
+            (&self.atimes, TimeField::Accessed, false),
+            (&self.ctimes, TimeField::Changed, false),
+        ];
+        for (tests, field, in_minutes) in times {
+            predicates.extend(tests.iter().map(|test| {
+                if in_minutes {
+                    Predicate::time_in_minutes(field, *test)
+                } else {
+                    Predicate::time_in_days(field, *test)
+                }
+            }));
+        }
+        if let Some(ref path) = self.newer {
+            let metadata = fs::metadata(path).context(IoPathSnafu { path })?;
+            predicates.push(Predicate::newer_than(&metadata));
+        }
+        if self.empty {
+            predicates.push(Predicate::Empty);
+        }
+        if let Some(perm) = self.perm {
+            predicates.push(Predicate::Perm(perm));
+        }
+        if let Some(uid) = self.user {
+            predicates.push(Predicate::User(uid));
+        }
+        if let Some(gid) = self.group {
+            predicates.push(Predicate::Group(gid));
+        }
+        // Reading contents is the slowest test, so it runs last
+        predicates.extend(self.contains.iter().cloned().map(Predicate::Contains));
+        predicates.extend(self.mimes.iter().cloned().map(Predicate::Mime));
 
-    let current_dir = PathBuf::from(".");
-    let mut paths = cli.paths;
-    if paths.is_empty() {
-        paths.push(current_dir.clone());
+        Ok(predicates)
     }
-    for path in paths {
-        let walker = WalkDir::new(path.clone()).follow_links(true);
-        for entry in walker {
-            match entry {
-                Err(err) => {
-                    eprint!("{}: {}", path.display(), err);
-                }
-                Ok(entry) => {
-                    let entry_type = {
-                        if entry.file_type().is_file() {
-                            Some(EntryType::File)
-                        } else if entry.file_type().is_dir() {
-                            Some(EntryType::Dir)
-                        } else if entry.file_type().is_symlink() {
-                            Some(EntryType::Link)
-                        } else {
-                            None
-                        }
-                    };
-                    if entry_type.is_none() {
-                        continue;
+
+    /// Collects the --exec, --execdir and --ok commands, which clap only keeps apart in `matches`
+    fn commands(matches: &ArgMatches) -> CliResult<Vec<Exec>> {
+        let mut commands = vec![];
+        for (id, in_dir, prompt) in [
+            ("exec", false, false),
+            ("execdir", true, false),
+            ("ok", false, true),
+        ] {
+            for command in matches.get_occurrences::<String>(id).into_iter().flatten() {
+                commands.push(Exec::new(command.cloned().collect(), in_dir, prompt)?);
+            }
+        }
+        Ok(commands)
+    }
+}
+
+/// The tests every entry goes through, shared by the walker threads
+struct Search {
+    cli: Cli,
+    predicates: Vec<Predicate>,
+    /// When the search started, in seconds since the epoch
+    now: i64,
+}
+
+impl Search {
+    fn is_match(&self, entry: &Entry) -> bool {
+        let entry_type = {
+            if entry.file_type().is_file() {
+                EntryType::File
+            } else if entry.file_type().is_dir() {
+                EntryType::Dir
+            } else if entry.file_type().is_symlink() {
+                EntryType::Link
+            } else {
+                return false;
+            }
+        };
+        if !self.cli.types.is_empty() && !self.cli.types.contains(&entry_type) {
+            return false;
+        }
+        let name = entry.file_name().as_bytes();
+        let path = entry.path().as_os_str().as_bytes();
+        let name_patterns = [&self.cli.names, &self.cli.inames, &self.cli.name_regexes];
+        let path_patterns = [&self.cli.path_globs, &self.cli.ipath_globs];
+        let path_regexes = [&self.cli.regexes, &self.cli.iregexes];
+        let pattern_tests = [
+            (&name, name_patterns.as_slice()),
+            (&path, path_patterns.as_slice()),
+            (&path, path_regexes.as_slice()),
+        ];
+        for (text, patterns) in pattern_tests {
+            if patterns.iter().any(|patterns| !patterns.is_empty())
+                && !patterns
+                    .iter()
+                    .copied()
+                    .flatten()
+                    .any(|pattern| pattern.is_match(text))
+            {
+                return false;
+            }
+        }
+        if self.predicates.is_empty() {
+            return true;
+        }
+        match entry.metadata() {
+            Ok(metadata) => self
+                .predicates
+                .iter()
+                .all(|predicate| predicate.matches(entry.path(), &metadata, self.now)),
+            Err(err) => {
+                report::error(entry.path(), err);
+                false
+            }
+        }
+    }
+
+    /// Returns whether the entry matches, and whether the walk should descend into it
+    fn visit(&self, entry: &Entry, exclusions: &mut Exclusions) -> (bool, bool) {
+        let is_dir = entry.file_type().is_dir();
+        if exclusions.is_excluded(entry.path(), is_dir, entry.depth()) {
+            return (false, false);
+        }
+        let matched = self.is_match(entry);
+        (matched, !(matched && self.cli.prune && is_dir))
+    }
+
+    fn exclusions(&self) -> Exclusions {
+        Exclusions::new(self.cli.ignore_vcs, self.cli.no_hidden)
+    }
+
+    fn walk_options(&self) -> WalkOptions {
+        WalkOptions {
+            follow_links: self.cli.follow_links,
+            follow_root_links: self.cli.follow_root_links,
+            same_file_system: self.cli.xdev,
+            min_depth: self.cli.min_depth.unwrap_or(0),
+            max_depth: self.cli.max_depth.unwrap_or(usize::MAX),
+        }
+    }
+
+    fn walk_sequential(&self, paths: &[PathBuf], contents_first: bool, actions: &mut Actions) {
+        let options = self.walk_options();
+        let mut exclusions = self.exclusions();
+        for path in paths {
+            let mut walker = WalkDir::new(path)
+                .follow_links(options.follow_links)
+                .follow_root_links(options.follow_links || options.follow_root_links)
+                .same_file_system(options.same_file_system)
+                .contents_first(contents_first)
+                .min_depth(options.min_depth)
+                .max_depth(options.max_depth);
+            if self.cli.sort {
+                walker = walker.sort_by_file_name();
+            }
+            let mut walker = walker.into_iter();
+            while let Some(entry) = walker.next() {
+                let entry = match entry {
+                    Ok(entry) => {
+                        let follow_link = options.follow_links
+                            || (options.follow_root_links && entry.depth() == 0);
+                        Entry::from_walkdir(entry, follow_link)
                     }
-                    if let Some(entry_type) = entry_type {
-                        if !cli.types.is_empty() && !cli.types.contains(&entry_type) {
+                    Err(err) => match Entry::from_walkdir_error(&err) {
+                        Some(entry) => entry,
+                        None => {
+                            walk::report_walkdir_error(&err);
                             continue;
                         }
-                    }
-                    // println!("{}",entry.path().to_string_lossy());
-                    if !cli.names.is_empty()
-                        && !cli
-                            .names
-                            .iter()
-                            .any(|pattern| pattern.is_match(&entry.file_name().to_string_lossy()))
-                    {
-                        continue;
-                    }
+                    },
+                };
+                let (matched, descend) = self.visit(&entry, &mut exclusions);
+                if !descend && !contents_first && entry.file_type().is_dir() {
+                    walker.skip_current_dir();
+                }
+                if matched {
+                    actions.apply(&self.cli, &entry);
+                }
+            }
+        }
+    }
 
-                    // Satify Windows tests that use mixed separators...
-                    let path = RelativePath::new(entry.path().to_str().unwrap());
-                    let parent = path
-                        .parent()
-                        .map(|p| p.as_str())
-                        .unwrap_or("")
-                        .replace(MAIN_SEPARATOR, "/");
-                    if parent.is_empty() {
-                        println!("{}", path.file_name().unwrap());
-                    } else {
-                        println!("{}/{}", parent, path.file_name().unwrap());
+    fn walk_parallel(&self, paths: &[PathBuf], threads: usize, actions: &mut Actions) {
+        let options = self.walk_options();
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                walk::walk_parallel(paths, &options, threads, || {
+                    let sender = sender.clone();
+                    let mut exclusions = self.exclusions();
+                    move |entry: &Entry| {
+                        let (matched, descend) = self.visit(entry, &mut exclusions);
+                        if matched {
+                            let _ = sender.send(entry.clone());
+                        }
+                        descend
                     }
+                });
+            });
+
+            if self.cli.sort {
+                let mut entries: Vec<Entry> = receiver.iter().collect();
+                entries.sort_by(|a, b| a.path().cmp(b.path()));
+                for entry in entries {
+                    actions.apply(&self.cli, &entry);
+                }
+            } else {
+                for entry in receiver {
+                    actions.apply(&self.cli, &entry);
                 }
             }
+        });
+    }
+}
+
+/// The actions taken on matches, always from the main thread
+struct Actions {
+    commands: Vec<Exec>,
+    print: bool,
+    failed: bool,
+}
+
+impl Actions {
+    fn apply(&mut self, cli: &Cli, entry: &Entry) {
+        // Like find, a failing command hides the match from the actions after it
+        for command in self.commands.iter_mut() {
+            if !command.run(entry.path()) {
+                if !command.prompt {
+                    self.failed = true;
+                }
+                return;
+            }
+        }
+
+        let path = entry.path().as_os_str().as_bytes();
+        if self.print {
+            actions::print_raw(&[path, b"\n"].concat());
+        }
+        if cli.print0 {
+            actions::print_raw(&[path, b"\0"].concat());
+        }
+        if let Some(ref format) = cli.printf {
+            actions::print_raw(&actions::format(format, entry));
+        }
+        if cli.json {
+            let fields = if cli.json_fields.is_empty() {
+                json::Field::value_variants()
+            } else {
+                &cli.json_fields
+            };
+            println!("{}", json::format(entry, fields));
+        }
+        if cli.delete
+            && let Err(err) = actions::delete(entry)
+        {
+            report::error(entry.path(), err);
+            self.failed = true;
+        }
+    }
+
+    /// Runs the pending batched commands, and returns whether any action failed
+    fn finish(mut self) -> bool {
+        for command in self.commands.iter_mut() {
+            if !command.finish() {
+                self.failed = true;
+            }
         }
+        self.failed
+    }
+}
+
+/// Terminates the batched `{} +` form of `--exec` so clap ends the command there too
+fn normalize_args<I: IntoIterator<Item = OsString>>(args: I) -> Vec<OsString> {
+    let mut normalized: Vec<OsString> = vec![];
+    let mut in_command = false;
+    for arg in args {
+        if in_command && arg == "+" && normalized.last().is_some_and(|prev| prev == "{}") {
+            normalized.push(arg);
+            normalized.push(";".into());
+            in_command = false;
+            continue;
+        }
+        if in_command && arg == ";" {
+            in_command = false;
+        } else if !in_command && (arg == "--exec" || arg == "--execdir" || arg == "--ok") {
+            in_command = true;
+        }
+        normalized.push(arg);
+    }
+    normalized
+}
+
+/// Prints an error returned by [`run`], as JSON with `--json`
+pub fn report_fatal(error: &CliError) {
+    report::fatal(error);
+}
+
+/// Returns the exit status: 1 if any command or deletion failed, 0 otherwise
+pub fn run() -> CliResult<i32> {
+    let matches = Cli::command().get_matches_from(normalize_args(std::env::args_os()));
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    report::set_json(cli.json);
+    let commands = Cli::commands(&matches)?;
+    let contents_first = cli.depth || cli.delete;
+    let print = cli.print
+        || !(cli.print0 || cli.printf.is_some() || cli.json || cli.delete || !commands.is_empty());
+    let threads = match cli.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let sequential = threads == 1 || contents_first || !cli.ok.is_empty();
+    let paths = if cli.paths.is_empty() {
+        vec![PathBuf::from(".")]
+    } else {
+        cli.paths.clone()
+    };
+    let search = Search {
+        predicates: cli.predicates()?,
+        now: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64),
+        cli,
+    };
+    let mut actions = Actions {
+        commands,
+        print,
+        failed: false,
+    };
+
+    if sequential {
+        search.walk_sequential(&paths, contents_first, &mut actions);
+    } else {
+        search.walk_parallel(&paths, threads, &mut actions);
     }
 
-    Ok(())
+    Ok(if actions.finish() { 1 } else { 0 })
 }
@@ -1,8 +1,11 @@
 use std::process;
 
 fn main() {
-    if let Err(error) = findr::run() {
-        eprint!("{error}");
-        process::exit(1)
+    match findr::run() {
+        Ok(status) => process::exit(status),
+        Err(error) => {
+            findr::report_fatal(&error);
+            process::exit(1)
+        }
     }
 }
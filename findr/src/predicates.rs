@@ -0,0 +1,403 @@
+use std::{
+    cmp::Ordering,
+    fs::{self, Metadata},
+    os::unix::fs::MetadataExt,
+    path::Path,
+    str::FromStr,
+};
+
+use regex::bytes::Regex;
+
+use crate::{
+    content::{self, FileKind, MimeTest},
+    report,
+};
+
+const SECONDS_PER_MINUTE: i64 = 60;
+const SECONDS_PER_DAY: i64 = 24 * 60 * SECONDS_PER_MINUTE;
+
+/// A numeric argument in find's `+N`/`-N`/`N` form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumTest {
+    ordering: Ordering,
+    n: i64,
+}
+
+impl NumTest {
+    fn matches(&self, value: i64) -> bool {
+        value.cmp(&self.n) == self.ordering
+    }
+
+    /// Splits the leading `+`/`-` off `s` and returns the comparison along with the rest
+    fn split(s: &str) -> (Ordering, &str) {
+        if let Some(rest) = s.strip_prefix('+') {
+            (Ordering::Greater, rest)
+        } else if let Some(rest) = s.strip_prefix('-') {
+            (Ordering::Less, rest)
+        } else {
+            (Ordering::Equal, s)
+        }
+    }
+
+    fn parse_number(s: &str, text: &str) -> Result<i64, String> {
+        if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("invalid number \"{text}\""));
+        }
+        s.parse().map_err(|_| format!("invalid number \"{text}\""))
+    }
+}
+
+impl FromStr for NumTest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ordering, rest) = Self::split(s);
+        Ok(Self {
+            ordering,
+            n: Self::parse_number(rest, s)?,
+        })
+    }
+}
+
+/// A `-size` argument: a [`NumTest`] counted in units of `unit` bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeTest {
+    test: NumTest,
+    unit: u64,
+}
+
+impl SizeTest {
+    fn matches(&self, size: u64) -> bool {
+        // Like find, sizes are rounded up to the next unit, so `-1M` only matches empty files
+        self.test.matches(size.div_ceil(self.unit) as i64)
+    }
+}
+
+impl FromStr for SizeTest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ordering, rest) = NumTest::split(s);
+        let (digits, unit) = match rest.char_indices().last() {
+            Some((i, c)) if !c.is_ascii_digit() => {
+                let unit = match c {
+                    'c' => 1,
+                    'w' => 2,
+                    'b' => 512,
+                    'k' => 1024,
+                    'M' => 1024 * 1024,
+                    'G' => 1024 * 1024 * 1024,
+                    _ => return Err(format!("invalid size unit '{c}' in \"{s}\"")),
+                };
+                (&rest[..i], unit)
+            }
+            // 512-byte blocks are the default unit
+            _ => (rest, 512),
+        };
+        Ok(Self {
+            test: NumTest {
+                ordering,
+                n: NumTest::parse_number(digits, s)?,
+            },
+            unit,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeField {
+    Accessed,
+    Changed,
+    Modified,
+}
+
+impl TimeField {
+    fn seconds(&self, metadata: &Metadata) -> i64 {
+        match self {
+            TimeField::Accessed => metadata.atime(),
+            TimeField::Changed => metadata.ctime(),
+            TimeField::Modified => metadata.mtime(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermMode {
+    /// Mode bits are exactly these
+    Exact,
+    /// All of these bits are set (`-MODE`)
+    All,
+    /// Any of these bits is set (`/MODE`)
+    Any,
+}
+
+/// A `-perm` argument, in octal or symbolic (`u=rwx,g+w`) notation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PermTest {
+    mode: PermMode,
+    bits: u32,
+}
+
+impl PermTest {
+    fn matches(&self, mode: u32) -> bool {
+        let mode = mode & 0o7777;
+        match self.mode {
+            PermMode::Exact => mode == self.bits,
+            PermMode::All => mode & self.bits == self.bits,
+            // find treats `/000` as matching everything
+            PermMode::Any => self.bits == 0 || mode & self.bits != 0,
+        }
+    }
+
+    fn parse_symbolic(s: &str) -> Option<u32> {
+        let mut bits = 0;
+        for clause in s.split(',') {
+            let ops_start = clause.find(['+', '-', '='])?;
+            let who = clause[..ops_start].chars().try_fold(0, |mask, c| match c {
+                'u' => Some(mask | 0o4700),
+                'g' => Some(mask | 0o2070),
+                'o' => Some(mask | 0o0007),
+                'a' => Some(mask | 0o7777),
+                _ => None,
+            })?;
+            let who = if who == 0 { 0o7777 } else { who };
+
+            let mut rest = &clause[ops_start..];
+            while let Some(op) = rest.chars().next() {
+                let perms_end = rest[1..]
+                    .find(['+', '-', '='])
+                    .map_or(rest.len(), |i| i + 1);
+                let perms = rest[1..perms_end].chars().try_fold(0, |mask, c| match c {
+                    'r' => Some(mask | 0o444),
+                    'w' => Some(mask | 0o222),
+                    'x' | 'X' => Some(mask | 0o111),
+                    's' => Some(mask | 0o6000),
+                    't' => Some(mask | 0o1000),
+                    _ => None,
+                })?;
+                match op {
+                    '+' => bits |= who & perms,
+                    '-' => bits &= !(who & perms),
+                    '=' => bits = (bits & !who) | (who & perms),
+                    _ => return None,
+                }
+                rest = &rest[perms_end..];
+            }
+        }
+        Some(bits)
+    }
+}
+
+impl FromStr for PermTest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mode, rest) = if let Some(rest) = s.strip_prefix('-') {
+            (PermMode::All, rest)
+        } else if let Some(rest) = s.strip_prefix('/') {
+            (PermMode::Any, rest)
+        } else {
+            (PermMode::Exact, s)
+        };
+        let bits = if !rest.is_empty() && rest.chars().all(|c| c.is_digit(8)) {
+            u32::from_str_radix(rest, 8)
+                .ok()
+                .filter(|bits| *bits <= 0o7777)
+        } else {
+            Self::parse_symbolic(rest)
+        };
+
+        bits.map(|bits| Self { mode, bits })
+            .ok_or_else(|| format!("invalid mode \"{s}\""))
+    }
+}
+
+/// Resolves a user name, falling back to a numeric user ID
+pub fn parse_user(s: &str) -> Result<u32, String> {
+    users::get_user_by_name(s)
+        .map(|user| user.uid())
+        .or_else(|| s.parse().ok())
+        .ok_or_else(|| format!("\"{s}\" is not the name of a known user"))
+}
+
+/// Resolves a group name, falling back to a numeric group ID
+pub fn parse_group(s: &str) -> Result<u32, String> {
+    users::get_group_by_name(s)
+        .map(|group| group.gid())
+        .or_else(|| s.parse().ok())
+        .ok_or_else(|| format!("\"{s}\" is not the name of a known group"))
+}
+
+/// A test on an entry's metadata
+#[derive(Debug, Clone)]
+pub enum Predicate {
+    Size(SizeTest),
+    /// Age of a timestamp, counted in units of `unit` seconds
+    Time {
+        field: TimeField,
+        unit: i64,
+        test: NumTest,
+    },
+    /// Modified more recently than this `(seconds, nanoseconds)` timestamp
+    Newer(i64, i64),
+    Empty,
+    Perm(PermTest),
+    User(u32),
+    Group(u32),
+    /// A regular file with a line that matches
+    Contains(Regex),
+    /// A regular file whose magic bytes match
+    Mime(MimeTest),
+}
+
+impl Predicate {
+    pub fn time_in_days(field: TimeField, test: NumTest) -> Self {
+        Predicate::Time {
+            field,
+            unit: SECONDS_PER_DAY,
+            test,
+        }
+    }
+
+    pub fn time_in_minutes(field: TimeField, test: NumTest) -> Self {
+        Predicate::Time {
+            field,
+            unit: SECONDS_PER_MINUTE,
+            test,
+        }
+    }
+
+    pub fn newer_than(metadata: &Metadata) -> Self {
+        Predicate::Newer(metadata.mtime(), metadata.mtime_nsec())
+    }
+
+    /// `now` is the time the search started, in seconds since the epoch
+    pub fn matches(&self, path: &Path, metadata: &Metadata, now: i64) -> bool {
+        match self {
+            Predicate::Size(test) => test.matches(metadata.size()),
+            Predicate::Time { field, unit, test } => {
+                let age = now - field.seconds(metadata);
+                test.matches(age.div_euclid(*unit))
+            }
+            Predicate::Newer(secs, nsecs) => {
+                (metadata.mtime(), metadata.mtime_nsec()) > (*secs, *nsecs)
+            }
+            Predicate::Empty => {
+                if metadata.is_dir() {
+                    fs::read_dir(path)
+                        .map(|mut entries| entries.next().is_none())
+                        .unwrap_or(false)
+                } else {
+                    metadata.is_file() && metadata.len() == 0
+                }
+            }
+            Predicate::Perm(test) => test.matches(metadata.mode()),
+            Predicate::User(uid) => metadata.uid() == *uid,
+            Predicate::Group(gid) => metadata.gid() == *gid,
+            Predicate::Contains(regex) => {
+                metadata.is_file()
+                    && content::contains(path, regex).unwrap_or_else(|err| {
+                        report::error(path, err);
+                        false
+                    })
+            }
+            Predicate::Mime(test) => {
+                metadata.is_file()
+                    && match FileKind::of_file(path) {
+                        Ok(kind) => test.matches(&kind),
+                        Err(err) => {
+                            report::error(path, err);
+                            false
+                        }
+                    }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        let size: SizeTest = "+100M".parse().unwrap();
+        assert!(size.matches(100 * 1024 * 1024 + 1));
+        assert!(!size.matches(100 * 1024 * 1024));
+
+        // Sizes are rounded up to whole units
+        let size: SizeTest = "-1M".parse().unwrap();
+        assert!(size.matches(0));
+        assert!(!size.matches(1));
+
+        let size: SizeTest = "2".parse().unwrap();
+        assert!(size.matches(1000));
+        assert!(!size.matches(1025));
+
+        let size: SizeTest = "10c".parse().unwrap();
+        assert!(size.matches(10));
+        assert!(!size.matches(11));
+
+        assert!("".parse::<SizeTest>().is_err());
+        assert!("+".parse::<SizeTest>().is_err());
+        assert!("1x".parse::<SizeTest>().is_err());
+        assert!("k".parse::<SizeTest>().is_err());
+        assert!("+-1k".parse::<SizeTest>().is_err());
+    }
+
+    #[test]
+    fn test_parse_num() {
+        let test: NumTest = "+30".parse().unwrap();
+        assert!(test.matches(31));
+        assert!(!test.matches(30));
+
+        let test: NumTest = "-1".parse().unwrap();
+        assert!(test.matches(0));
+        assert!(!test.matches(1));
+
+        let test: NumTest = "7".parse().unwrap();
+        assert!(test.matches(7));
+
+        assert!("a".parse::<NumTest>().is_err());
+        assert!("1.5".parse::<NumTest>().is_err());
+    }
+
+    #[test]
+    fn test_parse_perm() {
+        let perm: PermTest = "644".parse().unwrap();
+        assert_eq!(perm.mode, PermMode::Exact);
+        assert_eq!(perm.bits, 0o644);
+        assert!(perm.matches(0o100644));
+        assert!(!perm.matches(0o100664));
+
+        let perm: PermTest = "-220".parse().unwrap();
+        assert!(perm.matches(0o664));
+        assert!(!perm.matches(0o644));
+
+        let perm: PermTest = "/111".parse().unwrap();
+        assert!(perm.matches(0o744));
+        assert!(!perm.matches(0o644));
+        assert!("/000".parse::<PermTest>().unwrap().matches(0o644));
+
+        assert_eq!("u=rw,go=r".parse::<PermTest>().unwrap().bits, 0o644);
+        assert_eq!("-g+w".parse::<PermTest>().unwrap().bits, 0o020);
+        assert_eq!("a+rx-x".parse::<PermTest>().unwrap().bits, 0o444);
+        assert_eq!("+x".parse::<PermTest>().unwrap().bits, 0o111);
+        assert_eq!("u+s".parse::<PermTest>().unwrap().bits, 0o4000);
+
+        assert!("".parse::<PermTest>().is_err());
+        assert!("8".parse::<PermTest>().is_err());
+        assert!("17777".parse::<PermTest>().is_err());
+        assert!("u".parse::<PermTest>().is_err());
+        assert!("q+r".parse::<PermTest>().is_err());
+        assert!("u+q".parse::<PermTest>().is_err());
+    }
+
+    #[test]
+    fn test_parse_user() {
+        assert_eq!(parse_user("0"), Ok(0));
+        assert_eq!(parse_group("0"), Ok(0));
+        assert!(parse_user("no-such-user-here").is_err());
+        assert!(parse_group("no-such-group-here").is_err());
+    }
+}
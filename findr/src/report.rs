@@ -0,0 +1,56 @@
+use std::{
+    fmt::Display,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde_json::json;
+
+use crate::json::Data;
+
+/// Errors are written as JSON objects (`--json`)
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+/// Reports an error that stops the search
+pub fn fatal(err: impl Display) {
+    if JSON.load(Ordering::Relaxed) {
+        eprintln!("{}", json!({ "error": err.to_string() }));
+    } else {
+        eprint!("{err}");
+    }
+}
+
+/// Reports an error about `path` and carries on with the search
+pub fn error(path: &Path, err: impl Display) {
+    if JSON.load(Ordering::Relaxed) {
+        eprintln!(
+            "{}",
+            json!({ "path": Data::new(path), "error": err.to_string() })
+        );
+    } else {
+        eprintln!("{}: {}", path.display(), err);
+    }
+}
+
+/// Reports a followed link at `path` that leads back to `ancestor`
+pub fn file_system_loop(path: &Path, ancestor: &Path) {
+    if JSON.load(Ordering::Relaxed) {
+        eprintln!(
+            "{}",
+            json!({
+                "path": Data::new(path),
+                "error": format!("File system loop found: points to an ancestor {}", ancestor.display()),
+            })
+        );
+    } else {
+        eprintln!(
+            "File system loop found: {} points to an ancestor {}",
+            path.display(),
+            ancestor.display()
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    ffi::OsStr,
+    fs::{self, FileType, Metadata},
+    io, iter,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use crossbeam_deque::{Injector, Stealer, Worker};
+
+use crate::report;
+
+/// A file or directory found by either walker
+#[derive(Debug, Clone)]
+pub struct Entry {
+    path: PathBuf,
+    file_type: FileType,
+    depth: usize,
+    follow_link: bool,
+}
+
+impl Entry {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file_name(&self) -> &OsStr {
+        self.path.file_name().unwrap_or(self.path.as_os_str())
+    }
+
+    /// The type of the entry, or of its target if the link was followed
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        if self.follow_link {
+            fs::metadata(&self.path)
+        } else {
+            fs::symlink_metadata(&self.path)
+        }
+    }
+
+    /// `follow_link` says whether a link at this entry is followed. walkdir only reports
+    /// the target's type for links it followed itself, which leaves out `-H` search paths.
+    pub fn from_walkdir(entry: walkdir::DirEntry, follow_link: bool) -> Self {
+        let mut file_type = entry.file_type();
+        if follow_link
+            && file_type.is_symlink()
+            && let Ok(metadata) = fs::metadata(entry.path())
+        {
+            file_type = metadata.file_type();
+        }
+        Self {
+            file_type,
+            depth: entry.depth(),
+            follow_link,
+            path: entry.into_path(),
+        }
+    }
+
+    /// Recovers the broken link that walkdir fails to follow, which find reports as a link
+    pub fn from_walkdir_error(err: &walkdir::Error) -> Option<Self> {
+        let path = err.path()?;
+        if err.io_error()?.kind() != io::ErrorKind::NotFound {
+            return None;
+        }
+        let metadata = fs::symlink_metadata(path).ok()?;
+        metadata.file_type().is_symlink().then(|| Self {
+            path: path.to_path_buf(),
+            file_type: metadata.file_type(),
+            depth: err.depth(),
+            follow_link: false,
+        })
+    }
+}
+
+pub struct WalkOptions {
+    /// Follow symbolic links anywhere (`-L`)
+    pub follow_links: bool,
+    /// Follow symbolic links given as search paths (`-H`)
+    pub follow_root_links: bool,
+    pub same_file_system: bool,
+    pub min_depth: usize,
+    pub max_depth: usize,
+}
+
+/// A directory on the path from a search root, used to detect symlink loops
+struct Ancestor {
+    dev: u64,
+    ino: u64,
+    path: PathBuf,
+    parent: Option<Arc<Ancestor>>,
+}
+
+struct Job {
+    entry: Entry,
+    root_dev: u64,
+    ancestors: Option<Arc<Ancestor>>,
+}
+
+/// Where workers without a job sleep until another one has jobs to share or the walk ends,
+/// rather than spinning while slow directories are read
+#[derive(Default)]
+struct Idle {
+    /// Counts wakeups, so that a worker doesn't sleep through one that came after it last
+    /// looked for a job
+    wakeups: Mutex<u64>,
+    condvar: Condvar,
+    sleeping: AtomicUsize,
+}
+
+impl Idle {
+    fn lock(&self) -> MutexGuard<'_, u64> {
+        self.wakeups.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wakeups(&self) -> u64 {
+        *self.lock()
+    }
+
+    /// Sleeps unless there was a wakeup since `seen` or the walk is `done`
+    fn sleep(&self, seen: u64, done: impl Fn() -> bool) {
+        let wakeups = self.lock();
+        if *wakeups == seen && !done() {
+            self.sleeping.fetch_add(1, Ordering::SeqCst);
+            drop(
+                self.condvar
+                    .wait(wakeups)
+                    .unwrap_or_else(PoisonError::into_inner),
+            );
+            self.sleeping.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Wakes a sleeping worker to steal a job, or all of them when the walk is done
+    fn wake(&self, all: bool) {
+        if !all && self.sleeping.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        *self.lock() += 1;
+        match all {
+            true => self.condvar.notify_all(),
+            false => self.condvar.notify_one(),
+        }
+    }
+}
+
+/// Walks `roots` on `threads` threads that share directories through work-stealing queues.
+///
+/// Each thread calls `make_visitor` once, then calls the visitor on every entry it finds
+/// at `min_depth` or deeper. Directories are only descended into if the visitor returns true.
+pub fn walk_parallel<F, V>(
+    roots: &[PathBuf],
+    options: &WalkOptions,
+    threads: usize,
+    make_visitor: F,
+) where
+    F: Fn() -> V + Sync,
+    V: FnMut(&Entry) -> bool,
+{
+    let injector = Injector::new();
+    let pending = AtomicUsize::new(0);
+    for root in roots {
+        let follow_link = options.follow_links || options.follow_root_links;
+        let metadata = if follow_link {
+            fs::metadata(root)
+        } else {
+            fs::symlink_metadata(root)
+        };
+        match metadata {
+            Ok(metadata) => {
+                pending.fetch_add(1, Ordering::SeqCst);
+                injector.push(Job {
+                    entry: Entry {
+                        path: root.clone(),
+                        file_type: metadata.file_type(),
+                        depth: 0,
+                        follow_link,
+                    },
+                    root_dev: metadata.dev(),
+                    ancestors: None,
+                });
+            }
+            Err(err) => report::error(root, err),
+        }
+    }
+
+    let workers: Vec<Worker<Job>> = (0..threads).map(|_| Worker::new_lifo()).collect();
+    let stealers: Vec<Stealer<Job>> = workers.iter().map(Worker::stealer).collect();
+    let idle = Idle::default();
+    thread::scope(|scope| {
+        for worker in workers {
+            let (injector, stealers, pending, idle, make_visitor) =
+                (&injector, &stealers, &pending, &idle, &make_visitor);
+            scope.spawn(move || {
+                let mut visit = make_visitor();
+                let done = || pending.load(Ordering::SeqCst) == 0;
+                loop {
+                    let seen = idle.wakeups();
+                    match find_job(&worker, injector, stealers) {
+                        Some(job) => {
+                            process(job, options, &worker, pending, &mut visit);
+                            if pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                                idle.wake(true);
+                            } else if !worker.is_empty() {
+                                idle.wake(false);
+                            }
+                        }
+                        None if done() => break,
+                        None => idle.sleep(seen, done),
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn find_job(local: &Worker<Job>, global: &Injector<Job>, stealers: &[Stealer<Job>]) -> Option<Job> {
+    local.pop().or_else(|| {
+        iter::repeat_with(|| {
+            global
+                .steal_batch_and_pop(local)
+                .or_else(|| stealers.iter().map(Stealer::steal).collect())
+        })
+        .find(|steal| !steal.is_retry())
+        .and_then(|steal| steal.success())
+    })
+}
+
+/// Visits the job's entry and queues its children
+fn process<V: FnMut(&Entry) -> bool>(
+    job: Job,
+    options: &WalkOptions,
+    local: &Worker<Job>,
+    pending: &AtomicUsize,
+    visit: &mut V,
+) {
+    let Job {
+        entry,
+        root_dev,
+        ancestors,
+    } = job;
+    // Loops are found before the entry is visited, so that it's left out like walkdir does
+    let mut ancestors = ancestors;
+    let mut metadata = None;
+    if options.follow_links && entry.file_type.is_dir() {
+        let found = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                report::error(&entry.path, err);
+                return;
+            }
+        };
+        let mut ancestor = ancestors.as_deref();
+        while let Some(dir) = ancestor {
+            if (dir.dev, dir.ino) == (found.dev(), found.ino()) {
+                report::file_system_loop(&entry.path, &dir.path);
+                return;
+            }
+            ancestor = dir.parent.as_deref();
+        }
+        ancestors = Some(Arc::new(Ancestor {
+            dev: found.dev(),
+            ino: found.ino(),
+            path: entry.path.clone(),
+            parent: ancestors,
+        }));
+        metadata = Some(found);
+    }
+
+    if entry.depth >= options.min_depth && !visit(&entry) {
+        return;
+    }
+    if !entry.file_type.is_dir() || entry.depth >= options.max_depth {
+        return;
+    }
+    if options.same_file_system {
+        let metadata = match metadata.map_or_else(|| entry.metadata(), Ok) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                report::error(&entry.path, err);
+                return;
+            }
+        };
+        if metadata.dev() != root_dev {
+            return;
+        }
+    }
+
+    let children = match fs::read_dir(&entry.path) {
+        Ok(children) => children,
+        Err(err) => {
+            report::error(&entry.path, err);
+            return;
+        }
+    };
+    for child in children {
+        let child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                report::error(&entry.path, err);
+                continue;
+            }
+        };
+        let path = child.path();
+        let mut file_type = match child.file_type() {
+            Ok(file_type) => file_type,
+            Err(err) => {
+                report::error(&path, err);
+                continue;
+            }
+        };
+        if options.follow_links && file_type.is_symlink() {
+            // A broken link is reported as a link
+            if let Ok(metadata) = fs::metadata(&path) {
+                file_type = metadata.file_type();
+            }
+        }
+        pending.fetch_add(1, Ordering::SeqCst);
+        local.push(Job {
+            entry: Entry {
+                path,
+                file_type,
+                depth: entry.depth + 1,
+                follow_link: options.follow_links,
+            },
+            root_dev,
+            ancestors: ancestors.clone(),
+        });
+    }
+}
+
+/// Prints an error from the sequential walker the way the parallel walker reports its own
+pub fn report_walkdir_error(err: &walkdir::Error) {
+    match (err.path(), err.loop_ancestor(), err.io_error()) {
+        (Some(path), Some(ancestor), _) => report::file_system_loop(path, ancestor),
+        (Some(path), None, Some(io_error)) => report::error(path, io_error),
+        // walkdir gives a path to all of its errors
+        _ => report::error(Path::new(""), err),
+    }
+}
@@ -3,7 +3,14 @@ use predicates::prelude::*;
 use pretty_assertions::assert_eq;
 use rand::{self, Rng};
 use rand_distr::Alphanumeric;
-use std::{borrow::Cow, error::Error, fs};
+use std::{
+    borrow::Cow,
+    error::Error,
+    ffi::OsStr,
+    fs,
+    os::unix::{ffi::OsStrExt, fs::symlink},
+    path::Path,
+};
 
 const PRG: &str = "findr";
 
@@ -41,7 +48,7 @@ fn skips_bad_dir() -> Result<()> {
 #[test]
 fn dies_bad_name() -> Result<()> {
     Command::cargo_bin(PRG)?
-        .args(["--name", "*.csv"])
+        .args(["--name-regex", "*.csv"])
         .assert()
         .failure()
         .stderr(predicate::str::contains("error: invalid value '*.csv'"));
@@ -219,7 +226,7 @@ fn type_l() -> Result<()> {
 #[test]
 fn type_f_l() -> Result<()> {
     run(
-        &["tests/inputs", "-t", "l", "f"],
+        &["tests/inputs", "-t", "l", "-t", "f"],
         "tests/expected/type_f_l.txt",
     )
 }
@@ -228,7 +235,7 @@ fn type_f_l() -> Result<()> {
 #[test]
 fn name_csv() -> Result<()> {
     run(
-        &["tests/inputs", "-n", ".*[.]csv"],
+        &["tests/inputs", "-n", "*.csv"],
         "tests/expected/name_csv.txt",
     )
 }
@@ -237,7 +244,7 @@ fn name_csv() -> Result<()> {
 #[test]
 fn name_csv_mp3() -> Result<()> {
     run(
-        &["tests/inputs", "-n", ".*[.]csv", "-n", ".*[.]mp3"],
+        &["tests/inputs", "-n", "*.csv", "-n", "*.mp3"],
         "tests/expected/name_csv_mp3.txt",
     )
 }
@@ -246,7 +253,7 @@ fn name_csv_mp3() -> Result<()> {
 #[test]
 fn name_txt_path_a_d() -> Result<()> {
     run(
-        &["tests/inputs/a", "tests/inputs/d", "--name", ".*.txt"],
+        &["tests/inputs/a", "tests/inputs/d", "--name", "*.txt"],
         "tests/expected/name_txt_path_a_d.txt",
     )
 }
@@ -254,14 +261,17 @@ fn name_txt_path_a_d() -> Result<()> {
 // --------------------------------------------------
 #[test]
 fn name_a() -> Result<()> {
-    run(&["tests/inputs", "-n", "a"], "tests/expected/name_a.txt")
+    run(
+        &["tests/inputs", "--name-regex", "a"],
+        "tests/expected/name_a.txt",
+    )
 }
 
 // --------------------------------------------------
 #[test]
 fn type_f_name_a() -> Result<()> {
     run(
-        &["tests/inputs", "-t", "f", "-n", "a"],
+        &["tests/inputs", "-t", "f", "--name-regex", "a"],
         "tests/expected/type_f_name_a.txt",
     )
 }
@@ -270,17 +280,126 @@ fn type_f_name_a() -> Result<()> {
 #[test]
 fn type_d_name_a() -> Result<()> {
     run(
-        &["tests/inputs", "--type", "d", "--name", "a"],
+        &["tests/inputs", "--type", "d", "--name-regex", "a"],
         "tests/expected/type_d_name_a.txt",
     )
 }
 
+// --------------------------------------------------
+#[test]
+fn name_glob_is_anchored() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-n", ".csv"])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn iname_csv() -> Result<()> {
+    run(
+        &["tests/inputs", "--iname", "*.CSV"],
+        "tests/expected/name_csv.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn path_glob() -> Result<()> {
+    run(
+        &["tests/inputs", "--path", "*/b/*"],
+        "tests/expected/path_glob_b.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn ipath_glob() -> Result<()> {
+    run(
+        &["tests/inputs", "--ipath", "*/A/B"],
+        "tests/expected/ipath_glob_a_b.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn regex() -> Result<()> {
+    run(
+        &["tests/inputs", "--regex", r".*/[a-c]\.[a-z0-9]+"],
+        "tests/expected/regex_a_c.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn regex_is_anchored() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--regex", "a.txt"])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn iregex() -> Result<()> {
+    run(
+        &["tests/inputs", "--iregex", r".*\.CSV"],
+        "tests/expected/name_csv.txt",
+    )
+}
+
 // --------------------------------------------------
 #[test]
 fn path_g() -> Result<()> {
     run(&["tests/inputs/g.csv"], "tests/expected/path_g.txt")
 }
 
+// --------------------------------------------------
+#[test]
+fn maxdepth() -> Result<()> {
+    run(
+        &["tests/inputs", "--maxdepth", "1"],
+        "tests/expected/maxdepth_1.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn mindepth() -> Result<()> {
+    run(
+        &["tests/inputs", "--mindepth", "3"],
+        "tests/expected/mindepth_3.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn mindepth_maxdepth() -> Result<()> {
+    run(
+        &["tests/inputs", "--mindepth", "2", "--maxdepth", "2"],
+        "tests/expected/mindepth_2_maxdepth_2.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn name_prune() -> Result<()> {
+    run(
+        &["tests/inputs", "-n", "[ab]", "--prune"],
+        "tests/expected/name_a_b_prune.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn xdev() -> Result<()> {
+    run(&["tests/inputs", "--xdev"], "tests/expected/path1.txt")
+}
+
 // --------------------------------------------------
 #[test]
 #[cfg(not(windows))]
@@ -311,3 +430,932 @@ fn unreadable_dir() -> Result<()> {
     assert!(stderr.contains("cant-touch-this: Permission denied"));
     Ok(())
 }
+
+// --------------------------------------------------
+/// Builds a directory of entries with known sizes, times and modes
+fn metadata_fixture() -> Result<tempfile::TempDir> {
+    use std::{
+        fs::{File, Permissions},
+        os::unix::fs::PermissionsExt,
+        time::{Duration, SystemTime},
+    };
+
+    let dir = tempfile::tempdir()?;
+    let root = dir.path();
+    let files: [(&str, usize, u32); 6] = [
+        ("empty.txt", 0, 0o644),
+        ("small.txt", 10, 0o644),
+        ("big.bin", 3000, 0o644),
+        ("old.txt", 5, 0o644),
+        ("private.txt", 5, 0o600),
+        ("full_dir/script.sh", 20, 0o755),
+    ];
+    fs::create_dir(root.join("empty_dir"))?;
+    fs::create_dir(root.join("full_dir"))?;
+    for (name, size, mode) in files {
+        let path = root.join(name);
+        fs::write(&path, "x".repeat(size))?;
+        fs::set_permissions(&path, Permissions::from_mode(mode))?;
+    }
+    let forty_days_ago = SystemTime::now() - Duration::from_secs(40 * 24 * 60 * 60);
+    File::options()
+        .write(true)
+        .open(root.join("old.txt"))?
+        .set_modified(forty_days_ago)?;
+
+    Ok(dir)
+}
+
+// --------------------------------------------------
+/// Runs the program on `dir` and returns the sorted matches relative to it
+fn run_in(dir: &Path, args: &[&str]) -> Result<Vec<String>> {
+    let output = Command::cargo_bin(PRG)?
+        .arg(dir)
+        .args(args)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let prefix = format!("{}/", dir.display());
+    let mut lines: Vec<String> = String::from_utf8(output)?
+        .lines()
+        .map(|line| line.strip_prefix(&prefix).unwrap_or(".").to_string())
+        .collect();
+    lines.sort();
+    Ok(lines)
+}
+
+// --------------------------------------------------
+#[test]
+fn size() -> Result<()> {
+    let dir = metadata_fixture()?;
+    let root = dir.path();
+    assert_eq!(run_in(root, &["-t", "f", "--size", "+2k"])?, ["big.bin"]);
+    assert_eq!(run_in(root, &["--size", "10c"])?, ["small.txt"]);
+    assert_eq!(run_in(root, &["-t", "f", "--size", "-1k"])?, ["empty.txt"]);
+    assert_eq!(
+        run_in(root, &["-t", "f", "--size", "+5c", "--size", "-3000c"])?,
+        ["full_dir/script.sh", "small.txt"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_size() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--size", "10x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid size unit 'x'"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn mtime() -> Result<()> {
+    let dir = metadata_fixture()?;
+    let root = dir.path();
+    assert_eq!(run_in(root, &["--mtime", "+30"])?, ["old.txt"]);
+    assert_eq!(run_in(root, &["--mtime", "40"])?, ["old.txt"]);
+    assert!(!run_in(root, &["--mtime", "-1"])?.contains(&"old.txt".to_string()));
+    assert_eq!(run_in(root, &["--mmin", "+1440"])?, ["old.txt"]);
+    assert_eq!(run_in(root, &["--ctime", "+30"])?, Vec::<String>::new());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn newer() -> Result<()> {
+    let dir = metadata_fixture()?;
+    let root = dir.path();
+    let old = root.join("old.txt");
+    let found = run_in(root, &["-t", "f", "--newer", old.to_str().unwrap()])?;
+    assert_eq!(
+        found,
+        [
+            "big.bin",
+            "empty.txt",
+            "full_dir/script.sh",
+            "private.txt",
+            "small.txt"
+        ]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_newer() -> Result<()> {
+    let bad = gen_bad_file();
+    let expected = format!("{}: .* [(]os error 2[)]", &bad);
+    Command::cargo_bin(PRG)?
+        .args(["--newer", &bad])
+        .assert()
+        .failure()
+        .stderr(predicate::str::is_match(expected)?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty() -> Result<()> {
+    let dir = metadata_fixture()?;
+    assert_eq!(
+        run_in(dir.path(), &["--empty"])?,
+        ["empty.txt", "empty_dir"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn perm() -> Result<()> {
+    let dir = metadata_fixture()?;
+    let root = dir.path();
+    assert_eq!(run_in(root, &["--perm", "600"])?, ["private.txt"]);
+    assert_eq!(
+        run_in(root, &["-t", "f", "--perm", "/111"])?,
+        ["full_dir/script.sh"]
+    );
+    assert_eq!(
+        run_in(root, &["-t", "f", "--perm", "-u=x,g=x"])?,
+        ["full_dir/script.sh"]
+    );
+    assert_eq!(
+        run_in(root, &["-t", "f", "--perm", "-g+r", "--size", "-1k"])?,
+        ["empty.txt"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn user_group() -> Result<()> {
+    let dir = metadata_fixture()?;
+    let root = dir.path();
+    let all = run_in(root, &[])?;
+    let uid = Command::new("id").arg("-u").output()?.stdout;
+    let uid = String::from_utf8(uid)?;
+    assert_eq!(run_in(root, &["--user", uid.trim()])?, all);
+    let gid = Command::new("id").arg("-g").output()?.stdout;
+    let gid = String::from_utf8(gid)?;
+    assert_eq!(run_in(root, &["--group", gid.trim()])?, all);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_user() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--user", "no-such-user-here"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "\"no-such-user-here\" is not the name of a known user",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Returns the `true` or `false` binary from the hello crate, or the system one
+fn hello_bin(name: &str) -> String {
+    let path = Path::new(env!("CARGO_BIN_EXE_findr")).with_file_name(name);
+    if path.is_file() {
+        path.display().to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+// --------------------------------------------------
+fn sorted_lines(output: &[u8], separator: char) -> Result<Vec<String>> {
+    let mut lines: Vec<String> = String::from_utf8(output.to_vec())?
+        .split(separator)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect();
+    lines.sort();
+    Ok(lines)
+}
+
+// --------------------------------------------------
+#[test]
+fn print0() -> Result<()> {
+    let output = Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "-t", "f", "--print0"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        sorted_lines(&output.stdout, '\0')?,
+        [
+            "tests/inputs/a/a.txt",
+            "tests/inputs/a/b/b.csv",
+            "tests/inputs/a/b/c/c.mp3"
+        ]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn printf() -> Result<()> {
+    let output = Command::cargo_bin(PRG)?
+        .args([
+            "tests/inputs/a/b",
+            "-t",
+            "f",
+            "--printf",
+            "%f %h %s %y %%\\n",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        sorted_lines(&output.stdout, '\n')?,
+        [
+            "b.csv tests/inputs/a/b 2 f %",
+            "c.mp3 tests/inputs/a/b/c 2 f %"
+        ]
+    );
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a/b", "-t", "d", "--printf", "%y:%p\\t"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        sorted_lines(&output.stdout, '\t')?,
+        ["d:tests/inputs/a/b", "d:tests/inputs/a/b/c"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exec_true() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--exec", &hello_bin("true"), "{}", ";"])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exec_false() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--exec", &hello_bin("false"), "{}", ";"])
+        .args(["--print"])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exec_print() -> Result<()> {
+    let output = Command::cargo_bin(PRG)?
+        .args([
+            "tests/inputs/a",
+            "-t",
+            "f",
+            "--exec",
+            &hello_bin("true"),
+            ";",
+        ])
+        .arg("--print")
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        sorted_lines(&output.stdout, '\n')?,
+        [
+            "tests/inputs/a/a.txt",
+            "tests/inputs/a/b/b.csv",
+            "tests/inputs/a/b/c/c.mp3"
+        ]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exec_batch() -> Result<()> {
+    let output = Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "-t", "f", "--exec", "echo", "{}", "+"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert_eq!(stdout.lines().count(), 1);
+    let mut paths: Vec<&str> = stdout.split_whitespace().collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            "tests/inputs/a/a.txt",
+            "tests/inputs/a/b/b.csv",
+            "tests/inputs/a/b/c/c.mp3"
+        ]
+    );
+
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--exec", &hello_bin("false"), "{}", "+"])
+        .assert()
+        .code(1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn execdir_batch_false() -> Result<()> {
+    // The batch of the first directory runs when the walk reaches the second one, and its
+    // failure doesn't hide any entry from the actions after it
+    let output = Command::cargo_bin(PRG)?
+        .args(["tests/inputs/d", "-t", "f", "--sort", "--execdir"])
+        .args([&hello_bin("false"), "{}", "+", "--print"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        sorted_lines(&output.stdout, '\n')?,
+        [
+            "tests/inputs/d/d.tsv",
+            "tests/inputs/d/d.txt",
+            "tests/inputs/d/e/e.mp3"
+        ]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn execdir() -> Result<()> {
+    let output = Command::cargo_bin(PRG)?
+        .args([
+            "tests/inputs/a/b",
+            "-t",
+            "f",
+            "--execdir",
+            "echo",
+            "{}",
+            ";",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(sorted_lines(&output.stdout, '\n')?, ["./b.csv", "./c.mp3"]);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_missing_command() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--exec", "{}", "+"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("missing command"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn ok() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/g.csv", "--ok", "echo", "{}", ";"])
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout("tests/inputs/g.csv\n")
+        .stderr("< echo tests/inputs/g.csv > ? ");
+
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/g.csv", "--ok", "echo", "{}", ";"])
+        .write_stdin("n\n")
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn depth() -> Result<()> {
+    let output = Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "--depth"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines.last(), Some(&"tests/inputs/a"));
+    let position = |path| lines.iter().position(|line| *line == path);
+    assert!(position("tests/inputs/a/b/c/c.mp3") < position("tests/inputs/a/b/c"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn delete() -> Result<()> {
+    let dir = metadata_fixture()?;
+    let root = dir.path();
+    Command::cargo_bin(PRG)?
+        .arg(root)
+        .args(["--name", "*.txt", "--delete"])
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(
+        run_in(root, &[])?,
+        [
+            ".",
+            "big.bin",
+            "empty_dir",
+            "full_dir",
+            "full_dir/script.sh"
+        ]
+    );
+
+    Command::cargo_bin(PRG)?
+        .arg(root.join("full_dir"))
+        .arg("--delete")
+        .assert()
+        .success();
+    assert_eq!(run_in(root, &[])?, [".", "big.bin", "empty_dir"]);
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Builds a repository with nested ignore files and hidden entries
+fn ignore_fixture() -> Result<tempfile::TempDir> {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path();
+    let files = [
+        (".gitignore", "*.log\n!keep.log\nbuild/\n"),
+        (".ignore", "secret.txt\n"),
+        (".git/HEAD", "ref: refs/heads/main\n"),
+        (".git/info/exclude", "local.txt\n"),
+        (".hidden", ""),
+        ("a.log", ""),
+        ("keep.log", ""),
+        ("local.txt", ""),
+        ("main.rs", ""),
+        ("secret.txt", ""),
+        ("build/out.bin", ""),
+        ("src/.gitignore", "*.tmp\n!important.log\n"),
+        ("src/debug.log", ""),
+        ("src/important.log", ""),
+        ("src/lib.rs", ""),
+        ("src/scratch.tmp", ""),
+    ];
+    for (name, contents) in files {
+        let path = root.join(name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, contents)?;
+    }
+    Ok(dir)
+}
+
+// --------------------------------------------------
+#[test]
+fn ignore_vcs() -> Result<()> {
+    let dir = ignore_fixture()?;
+    let expected = [
+        ".",
+        ".gitignore",
+        ".hidden",
+        ".ignore",
+        "keep.log",
+        "main.rs",
+        "src",
+        "src/.gitignore",
+        "src/important.log",
+        "src/lib.rs",
+    ];
+    assert_eq!(run_in(dir.path(), &["--ignore-vcs"])?, expected);
+    assert_eq!(run_in(dir.path(), &["--ignore-vcs", "--depth"])?, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_ignore() -> Result<()> {
+    let dir = ignore_fixture()?;
+    assert_eq!(
+        run_in(dir.path(), &["--ignore-vcs", "--no-ignore"])?,
+        run_in(dir.path(), &[])?
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_hidden() -> Result<()> {
+    let dir = ignore_fixture()?;
+    let expected = [
+        ".",
+        "a.log",
+        "build",
+        "build/out.bin",
+        "keep.log",
+        "local.txt",
+        "main.rs",
+        "secret.txt",
+        "src",
+        "src/debug.log",
+        "src/important.log",
+        "src/lib.rs",
+        "src/scratch.tmp",
+    ];
+    assert_eq!(run_in(dir.path(), &["--no-hidden"])?, expected);
+    assert_eq!(run_in(dir.path(), &["--no-hidden", "--depth"])?, expected);
+    assert_eq!(
+        run_in(dir.path(), &["--no-hidden", "--ignore-vcs"])?,
+        [
+            ".",
+            "keep.log",
+            "main.rs",
+            "src",
+            "src/important.log",
+            "src/lib.rs"
+        ]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn threads() -> Result<()> {
+    run(
+        &["tests/inputs", "--threads", "4"],
+        "tests/expected/path1.txt",
+    )?;
+    run(
+        &[
+            "tests/inputs/a/b",
+            "tests/inputs/d",
+            "--threads",
+            "0",
+            "-t",
+            "d",
+        ],
+        "tests/expected/type_d_path_a_b_d.txt",
+    )?;
+    run(
+        &["tests/inputs", "--threads", "4", "--name", "*.csv"],
+        "tests/expected/name_csv.txt",
+    )?;
+    run(
+        &["tests/inputs", "--threads", "4", "--maxdepth", "1"],
+        "tests/expected/maxdepth_1.txt",
+    )?;
+    run(
+        &[
+            "tests/inputs",
+            "--threads",
+            "4",
+            "--name",
+            "a",
+            "--name",
+            "b",
+            "--prune",
+        ],
+        "tests/expected/name_a_b_prune.txt",
+    )?;
+
+    let dir = ignore_fixture()?;
+    assert_eq!(
+        run_in(
+            dir.path(),
+            &["--threads", "4", "--ignore-vcs", "--no-hidden"]
+        )?,
+        run_in(dir.path(), &["--ignore-vcs", "--no-hidden"])?
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn sort() -> Result<()> {
+    let sequential = Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--sort"])
+        .output()?;
+    let parallel = Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--sort", "--threads", "4"])
+        .output()?;
+    let expected = [
+        "tests/inputs",
+        "tests/inputs/a",
+        "tests/inputs/a/a.txt",
+        "tests/inputs/a/b",
+        "tests/inputs/a/b/b.csv",
+    ];
+    let stdout = String::from_utf8(sequential.stdout)?;
+    assert_eq!(
+        stdout.lines().take(expected.len()).collect::<Vec<_>>(),
+        expected
+    );
+    assert_eq!(String::from_utf8(parallel.stdout)?, stdout);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn non_utf8_name() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let name = OsStr::from_bytes(b"caf\xe9.txt");
+    fs::write(dir.path().join(name), "")?;
+
+    let output = Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args([".", "-t", "f", "--name", "caf?.txt"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert_eq!(output, b"./caf\xe9.txt\n");
+
+    let output = Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args([".", "-t", "f", "--printf", "%f|%h\\n"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert_eq!(output, b"caf\xe9.txt|.\n");
+
+    // JSON keeps the bytes of the name in base64
+    symlink(name, dir.path().join("link"))?;
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args([
+            ".",
+            "-t",
+            "l",
+            "--json",
+            "--json-fields",
+            "path,name,target",
+        ])
+        .assert()
+        .success()
+        .stdout(concat!(
+            r#"{"path":"./link","name":"link","#,
+            r#""target":{"bytes":"Y2Fm6S50eHQ="}}"#,
+            "\n"
+        ));
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args([".", "-t", "f", "--json", "--json-fields", "path,name"])
+        .assert()
+        .success()
+        .stdout(concat!(
+            r#"{"path":{"bytes":"Li9jYWbpLnR4dA=="},"#,
+            r#""name":{"bytes":"Y2Fm6S50eHQ="}}"#,
+            "\n"
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Builds a tree with a link to a directory, a link back to the root and a broken link
+fn symlink_fixture() -> Result<tempfile::TempDir> {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path();
+    fs::create_dir_all(root.join("real/sub"))?;
+    fs::write(root.join("real/sub/file.txt"), "")?;
+    symlink("real", root.join("link"))?;
+    symlink("..", root.join("real/up"))?;
+    symlink("missing", root.join("broken"))?;
+    Ok(dir)
+}
+
+// --------------------------------------------------
+#[test]
+fn symlinks_not_followed() -> Result<()> {
+    let dir = symlink_fixture()?;
+    let expected = [
+        ".",
+        "broken",
+        "link",
+        "real",
+        "real/sub",
+        "real/sub/file.txt",
+        "real/up",
+    ];
+    assert_eq!(run_in(dir.path(), &[])?, expected);
+    assert_eq!(run_in(dir.path(), &["-P"])?, expected);
+    assert_eq!(
+        run_in(dir.path(), &["-t", "l"])?,
+        ["broken", "link", "real/up"]
+    );
+    assert_eq!(
+        run_in(dir.path(), &["-t", "l", "--threads", "4"])?,
+        ["broken", "link", "real/up"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn symlinks_followed() -> Result<()> {
+    let dir = symlink_fixture()?;
+    // The loops are left out whichever walker is used
+    let sequential = run_in(dir.path(), &["-L", "--threads", "1"])?;
+    assert!(!sequential.iter().any(|path| path.ends_with("up")));
+    for threads in ["2", "4"] {
+        assert_eq!(
+            run_in(dir.path(), &["-L", "--threads", threads])?,
+            sequential
+        );
+    }
+    for threads in ["1", "4"] {
+        let cmd = Command::cargo_bin(PRG)?
+            .arg(dir.path())
+            .args(["-L", "--threads", threads])
+            .assert()
+            .success();
+        let stderr = String::from_utf8(cmd.get_output().stderr.clone())?;
+        assert!(stderr.contains("File system loop found"));
+
+        assert_eq!(
+            run_in(dir.path(), &["-L", "-t", "l", "--threads", threads])?,
+            ["broken"]
+        );
+        assert_eq!(
+            run_in(
+                dir.path(),
+                &["-L", "--name", "file.txt", "--threads", threads]
+            )?,
+            ["link/sub/file.txt", "real/sub/file.txt"]
+        );
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn symlinks_followed_on_command_line() -> Result<()> {
+    let dir = symlink_fixture()?;
+    let link = dir.path().join("link");
+    for threads in ["1", "4"] {
+        assert_eq!(
+            run_in(&link, &["-H", "--threads", threads])?,
+            [".", "sub", "sub/file.txt", "up"]
+        );
+        assert_eq!(
+            run_in(&link, &["-H", "-t", "l", "--threads", threads])?,
+            ["up"]
+        );
+        assert_eq!(run_in(&link, &["--threads", threads])?, ["."]);
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json() -> Result<()> {
+    let dir = symlink_fixture()?;
+    fs::write(dir.path().join("real/sub/file.txt"), "hello")?;
+    let output = Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args([".", "--json", "--sort"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let records: Vec<serde_json::Value> = String::from_utf8(output)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<std::result::Result<_, _>>()?;
+    assert_eq!(records.len(), 7);
+
+    let file = &records[5];
+    let keys: Vec<&str> = file
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    let mut expected_keys = [
+        "path", "name", "type", "size", "mode", "uid", "gid", "mtime", "depth", "target",
+    ];
+    expected_keys.sort();
+    assert_eq!(keys, expected_keys);
+    assert_eq!(file["path"], "./real/sub/file.txt");
+    assert_eq!(file["name"], "file.txt");
+    assert_eq!(file["type"], "file");
+    assert_eq!(file["size"], 5);
+    assert_eq!(file["depth"], 3);
+    assert!(file["target"].is_null());
+
+    let link = &records[2];
+    assert_eq!(link["path"], "./link");
+    assert_eq!(link["type"], "symlink");
+    assert_eq!(link["target"], "real");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json_fields() -> Result<()> {
+    let dir = symlink_fixture()?;
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args(["link", "--json", "--json-fields", "type,target,path"])
+        .assert()
+        .success()
+        .stdout("{\"path\":\"link\",\"type\":\"symlink\",\"target\":\"real\"}\n");
+    Command::cargo_bin(PRG)?
+        .args(["--json-fields", "path"])
+        .assert()
+        .failure();
+    Command::cargo_bin(PRG)?
+        .args(["--json", "--json-fields", "color"])
+        .assert()
+        .failure();
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json_errors() -> Result<()> {
+    let bad = gen_bad_file();
+    let cmd = Command::cargo_bin(PRG)?
+        .args([&bad, "--json"])
+        .assert()
+        .success();
+    let stderr = String::from_utf8(cmd.get_output().stderr.clone())?;
+    let error: serde_json::Value = serde_json::from_str(stderr.trim_end())?;
+    assert_eq!(error["path"], bad.as_str());
+    assert!(error["error"].as_str().unwrap().contains("No such file"));
+
+    Command::cargo_bin(PRG)?
+        .args(["--json", "--newer", &bad])
+        .assert()
+        .failure()
+        .stderr(predicate::str::starts_with("{\"error\":"));
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Builds a directory of text, image and executable files
+fn content_fixture() -> Result<tempfile::TempDir> {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path();
+    fs::write(root.join("notes.txt"), "first line\nTODO: write tests\n")?;
+    fs::write(root.join("done.txt"), "last line\n")?;
+    fs::write(root.join("image.dat"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")?;
+    fs::write(root.join("blob.bin"), b"\x01\x02\0\x03")?;
+    fs::create_dir(root.join("TODO"))?;
+    fs::copy(env!("CARGO_BIN_EXE_findr"), root.join("program"))?;
+    Ok(dir)
+}
+
+// --------------------------------------------------
+#[test]
+fn contains() -> Result<()> {
+    let dir = content_fixture()?;
+    assert_eq!(run_in(dir.path(), &["--contains", "^TODO"])?, ["notes.txt"]);
+    assert_eq!(
+        run_in(dir.path(), &["--contains", "line", "--name", "*.txt"])?,
+        ["done.txt", "notes.txt"]
+    );
+    assert_eq!(
+        run_in(dir.path(), &["--contains", "line", "--contains", "^first"])?,
+        ["notes.txt"]
+    );
+    // Anchors match at the end of lines, whether they end with \n or \r\n
+    fs::write(dir.path().join("dos.txt"), "foo bar\r\nbaz\r\n")?;
+    assert_eq!(
+        run_in(dir.path(), &["--contains", "line$"])?,
+        ["done.txt", "notes.txt"]
+    );
+    assert_eq!(run_in(dir.path(), &["--contains", "bar$"])?, ["dos.txt"]);
+    assert_eq!(run_in(dir.path(), &["--contains", "^baz$"])?, ["dos.txt"]);
+    assert_eq!(
+        run_in(dir.path(), &["--contains", "^TODO.*s$"])?,
+        ["notes.txt"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn mime() -> Result<()> {
+    let dir = content_fixture()?;
+    assert_eq!(run_in(dir.path(), &["--mime", "image"])?, ["image.dat"]);
+    assert_eq!(run_in(dir.path(), &["--mime", "image/*"])?, ["image.dat"]);
+    assert_eq!(run_in(dir.path(), &["--mime", "elf"])?, ["program"]);
+    assert_eq!(run_in(dir.path(), &["--mime", "binary"])?, ["blob.bin"]);
+    assert_eq!(
+        run_in(dir.path(), &["--mime", "text"])?,
+        ["done.txt", "notes.txt"]
+    );
+    assert_eq!(
+        run_in(dir.path(), &["--mime", "text/plain", "--contains", "TODO"])?,
+        ["notes.txt"]
+    );
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use ansi_term::{Colour, Style};
+use clap::ValueEnum;
+
+/// The colours of SGR codes 30 to 37 and 40 to 47
+const BASIC_COLOURS: [Colour; 8] = [
+    Colour::Black,
+    Colour::Red,
+    Colour::Green,
+    Colour::Yellow,
+    Colour::Blue,
+    Colour::Purple,
+    Colour::Cyan,
+    Colour::White,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Highlight when writing to a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+/// The styles of each part of the output, set by `GREP_COLORS`. The default styles nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Colors {
+    /// Matches in selected lines (`ms`)
+    pub selected_match: Style,
+    /// Matches in context lines (`mc`)
+    pub context_match: Style,
+    /// File names (`fn`)
+    pub file_name: Style,
+    /// Line numbers (`ln`)
+    pub line_number: Style,
+    /// Byte offsets (`bn`)
+    pub byte_offset: Style,
+    /// Separators between prefixes and lines, and between groups of context (`se`)
+    pub separator: Style,
+}
+
+impl Colors {
+    /// The styles GNU grep uses when `GREP_COLORS` is unset
+    pub fn standard() -> Self {
+        let matched = Colour::Red.bold();
+        Self {
+            selected_match: matched,
+            context_match: matched,
+            file_name: Colour::Purple.normal(),
+            line_number: Colour::Green.normal(),
+            byte_offset: Colour::Green.normal(),
+            separator: Colour::Cyan.normal(),
+        }
+    }
+
+    /// Overrides the default styles with a spec like `ms=01;31:fn=35`. Unknown capabilities
+    /// are ignored, as grep does.
+    pub fn parse(spec: &str) -> Self {
+        let mut colors = Self::standard();
+        for (name, sgr) in spec.split(':').filter_map(|cap| cap.split_once('=')) {
+            let style = parse_sgr(sgr);
+            match name {
+                "mt" => {
+                    colors.selected_match = style;
+                    colors.context_match = style;
+                }
+                "ms" => colors.selected_match = style,
+                "mc" => colors.context_match = style,
+                "fn" => colors.file_name = style,
+                "ln" => colors.line_number = style,
+                "bn" => colors.byte_offset = style,
+                "se" => colors.separator = style,
+                _ => {}
+            }
+        }
+        colors
+    }
+}
+
+/// Converts Select Graphic Rendition parameters like `01;31` to a style
+fn parse_sgr(sgr: &str) -> Style {
+    let mut style = Style::new();
+    let mut codes = sgr.split(';').map(|code| code.parse::<u8>().unwrap_or(0));
+    while let Some(code) = codes.next() {
+        match code {
+            0 => style = Style::new(),
+            1 => style = style.bold(),
+            2 => style = style.dimmed(),
+            3 => style = style.italic(),
+            4 => style = style.underline(),
+            5 => style = style.blink(),
+            7 => style = style.reverse(),
+            8 => style = style.hidden(),
+            9 => style = style.strikethrough(),
+            30..=37 => style = style.fg(BASIC_COLOURS[usize::from(code - 30)]),
+            40..=47 => style = style.on(BASIC_COLOURS[usize::from(code - 40)]),
+            90..=97 => style = style.fg(Colour::Fixed(code - 90 + 8)),
+            100..=107 => style = style.on(Colour::Fixed(code - 100 + 8)),
+            38 | 48 => {
+                let colour = match codes.next() {
+                    Some(5) => codes.next().map(Colour::Fixed),
+                    Some(2) => match (codes.next(), codes.next(), codes.next()) {
+                        (Some(r), Some(g), Some(b)) => Some(Colour::RGB(r, g, b)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some(colour) = colour {
+                    style = if code == 38 {
+                        style.fg(colour)
+                    } else {
+                        style.on(colour)
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+    style
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sgr() {
+        assert_eq!(parse_sgr("01;31"), Colour::Red.bold());
+        assert_eq!(
+            parse_sgr("4;92"),
+            Style::new().underline().fg(Colour::Fixed(10))
+        );
+        assert_eq!(parse_sgr("38;5;208"), Style::new().fg(Colour::Fixed(208)));
+        assert_eq!(
+            parse_sgr("48;2;1;2;3;1"),
+            Style::new().on(Colour::RGB(1, 2, 3)).bold()
+        );
+        assert_eq!(parse_sgr(""), Style::new());
+    }
+
+    #[test]
+    fn test_parse_colors() {
+        let colors = Colors::parse("mt=01;32:fn=:xx=1:ln");
+        assert_eq!(colors.selected_match, Colour::Green.bold());
+        assert_eq!(colors.context_match, colors.selected_match);
+        assert_eq!(colors.file_name, Style::new());
+        assert_eq!(colors.line_number, Colors::standard().line_number);
+    }
+}
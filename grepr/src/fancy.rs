@@ -0,0 +1,301 @@
+use std::{borrow::Cow, ops::Range};
+
+use fancy_regex::{Error, Regex, RegexBuilder, RuntimeError};
+use snafu::ResultExt;
+
+use crate::{
+    CliResult, FancyRegexSnafu,
+    matcher::{self, Engine, MatchError, MatchOptions, MatchResult},
+    replace::Template,
+};
+
+/// How many steps a search may backtrack before giving up, so that patterns like `(a*)*b`
+/// can't take exponential time
+const BACKTRACK_LIMIT: usize = 1_000_000;
+
+/// Matches regexes with backreferences and lookaround with `fancy-regex`
+pub struct FancyEngine {
+    regex: Regex,
+    /// The terminator of the lines in the text with `-U`, when it isn't a newline, which
+    /// `fancy-regex` can't match `^` and `$` at. Each line is searched on its own instead.
+    lines: Option<u8>,
+}
+
+impl FancyEngine {
+    /// Builds the alternation of `patterns`, which are rewrites of `originals`
+    pub fn new(
+        patterns: &[String],
+        originals: &[String],
+        options: MatchOptions,
+    ) -> CliResult<Self> {
+        let pattern = matcher::alternation(&renumber_groups(patterns));
+        let pattern = if options.line {
+            format!("^(?:{pattern})$")
+        } else if options.word {
+            format!(r"(?<!\w)(?:{pattern})(?!\w)")
+        } else {
+            pattern
+        };
+        let newline = options.line_terminator == b'\n';
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(options.ignore_case)
+            .multi_line(options.multi_line && newline)
+            // Newlines are like any other character in lines that end with something else
+            .dot_matches_new_line(!newline)
+            .backtrack_limit(BACKTRACK_LIMIT)
+            .build()
+            .with_context(|_| FancyRegexSnafu {
+                pattern: matcher::invalid_pattern(patterns, originals, |pattern| {
+                    Regex::new(pattern).is_ok()
+                }),
+            })?;
+        Ok(Self {
+            regex,
+            lines: (options.multi_line && !newline).then_some(options.line_terminator),
+        })
+    }
+
+    /// Splits text into the parts that are searched separately, with their offsets
+    fn parts<'a>(&self, text: &'a [u8]) -> Vec<(usize, &'a [u8])> {
+        let Some(terminator) = self.lines else {
+            return vec![(0, text)];
+        };
+        let mut offset = 0;
+        text.split(|&byte| byte == terminator)
+            .map(|line| {
+                let start = offset;
+                offset += line.len() + 1;
+                (start, line)
+            })
+            .collect()
+    }
+}
+
+impl Engine for FancyEngine {
+    fn find_iter(&self, text: &[u8]) -> MatchResult<Vec<Range<usize>>> {
+        let mut matches = vec![];
+        for (start, part) in self.parts(text) {
+            let decoded = Decoded::new(part);
+            for found in self.regex.find_iter(&decoded.text) {
+                let found = decoded.range(found.map_err(match_error)?.range());
+                matches.push(start + found.start..start + found.end);
+            }
+        }
+        Ok(matches)
+    }
+
+    fn replacement(
+        &self,
+        text: &[u8],
+        found: Range<usize>,
+        template: &Template,
+    ) -> MatchResult<Vec<u8>> {
+        let Some((start, part)) = self
+            .parts(text)
+            .into_iter()
+            .find(|(start, part)| *start <= found.start && found.end <= start + part.len())
+        else {
+            return Ok(matcher::whole_match(text, found, template));
+        };
+        let found = found.start - start..found.end - start;
+        let decoded = Decoded::new(part);
+        let caps = self
+            .regex
+            .captures_from_pos(&decoded.text, decoded.position(found.start))
+            .map_err(match_error)?;
+        let group = |group: fancy_regex::Match| &part[decoded.range(group.range())];
+        Ok(match caps {
+            Some(caps)
+                if caps
+                    .get(0)
+                    .is_some_and(|whole| decoded.range(whole.range()) == found) =>
+            {
+                template.expand(
+                    |i| caps.get(i).map(group),
+                    |name| caps.name(name).map(group),
+                )
+            }
+            _ => matcher::whole_match(part, found, template),
+        })
+    }
+}
+
+/// Text decoded for `fancy-regex`, which only searches UTF-8. Each invalid byte is replaced
+/// with U+FFFD, so that anchors and lookaround still see the whole line.
+struct Decoded<'a> {
+    text: Cow<'a, str>,
+    /// The offset in the original bytes of each byte of the decoded text and of its end, if
+    /// anything was replaced
+    offsets: Option<Vec<usize>>,
+}
+
+impl<'a> Decoded<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        if let Ok(text) = str::from_utf8(bytes) {
+            return Self {
+                text: Cow::Borrowed(text),
+                offsets: None,
+            };
+        }
+        let mut text = String::with_capacity(bytes.len() + 2);
+        let mut offsets = Vec::with_capacity(bytes.len() + 3);
+        let mut offset = 0;
+        for chunk in bytes.utf8_chunks() {
+            text.push_str(chunk.valid());
+            offsets.extend(offset..offset + chunk.valid().len());
+            offset += chunk.valid().len();
+            for _ in chunk.invalid() {
+                text.push(char::REPLACEMENT_CHARACTER);
+                offsets.extend([offset; 3]);
+                offset += 1;
+            }
+        }
+        offsets.push(offset);
+        Self {
+            text: Cow::Owned(text),
+            offsets: Some(offsets),
+        }
+    }
+
+    /// Maps a range of the decoded text to the original bytes
+    fn range(&self, range: Range<usize>) -> Range<usize> {
+        match &self.offsets {
+            Some(offsets) => offsets[range.start]..offsets[range.end],
+            None => range,
+        }
+    }
+
+    /// Maps an offset in the original bytes to the decoded text
+    fn position(&self, offset: usize) -> usize {
+        match &self.offsets {
+            Some(offsets) => offsets.partition_point(|&o| o < offset),
+            None => offset,
+        }
+    }
+}
+
+/// Renumbers the backreferences of each pattern past the groups of the patterns before it,
+/// since they're matched as alternatives of a single regex
+fn renumber_groups(patterns: &[String]) -> Vec<String> {
+    let mut offset = 0;
+    patterns
+        .iter()
+        .map(|pattern| {
+            let (renumbered, groups) = shift_backreferences(pattern, offset);
+            offset += groups;
+            renumbered
+        })
+        .collect()
+}
+
+/// Adds `offset` to the numbered backreferences of a pattern, `\N` and `\k<N>`, returning it
+/// with the number of groups it has
+fn shift_backreferences(pattern: &str, offset: usize) -> (String, usize) {
+    let mut out = String::with_capacity(pattern.len());
+    let mut groups = 0;
+    let mut in_class = false;
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '\\' => {
+                let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+                let named = rest
+                    .strip_prefix("k<")
+                    .and_then(|after| after.split_once('>'))
+                    .and_then(|(group, after)| Some((group.parse::<usize>().ok()?, after)));
+                if !in_class && digits > 0 && !rest.starts_with('0') {
+                    let group: usize = rest[..digits].parse().unwrap_or_default();
+                    out.push_str(&format!(r"\k<{}>", group + offset));
+                    rest = &rest[digits..];
+                } else if !in_class && let Some((group, after)) = named {
+                    out.push_str(&format!(r"\k<{}>", group + offset));
+                    rest = after;
+                } else {
+                    // Whatever is escaped is kept as it is
+                    out.push('\\');
+                    if let Some(next) = rest.chars().next() {
+                        out.push(next);
+                        rest = &rest[next.len_utf8()..];
+                    }
+                }
+                continue;
+            }
+            '[' if !in_class => {
+                in_class = true;
+                out.push('[');
+                // A bracket that comes first is literal
+                for prefix in ["^]", "^", "]"] {
+                    if let Some(after) = rest.strip_prefix(prefix) {
+                        out.push_str(prefix);
+                        rest = after;
+                        break;
+                    }
+                }
+                continue;
+            }
+            // Classes like [:alpha:] don't end the class they're in
+            '[' if rest.starts_with(':') && rest.contains(":]") => {
+                let end = rest.find(":]").unwrap_or_default() + 2;
+                out.push('[');
+                out.push_str(&rest[..end]);
+                rest = &rest[end..];
+                continue;
+            }
+            ']' if in_class => in_class = false,
+            // Named groups are numbered too, unlike lookbehind and other groups with `?`
+            '(' if !in_class
+                && (!rest.starts_with('?')
+                    || rest.starts_with("?P<")
+                    || (rest.starts_with("?<")
+                        && !rest.starts_with("?<=")
+                        && !rest.starts_with("?<!"))) =>
+            {
+                groups += 1
+            }
+            _ => {}
+        }
+        out.push(c);
+    }
+    (out, groups)
+}
+
+fn match_error(err: Error) -> MatchError {
+    MatchError(match err {
+        Error::RuntimeError(RuntimeError::BacktrackLimitExceeded) => {
+            format!("Pattern backtracked more than {BACKTRACK_LIMIT} times")
+        }
+        err => err.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_shift_backreferences() {
+        let shift = |pattern: &str| shift_backreferences(pattern, 2);
+        assert_eq!(
+            shift(r"(a)(?:b)(?<c>c)(?P<d>d)\1\k<3>"),
+            (r"(a)(?:b)(?<c>c)(?P<d>d)\k<3>\k<5>".to_string(), 3)
+        );
+        assert_eq!(
+            shift(r"(?<=x)(?<!y)[(\1][]1][[:digit:](]\(\\1\0\k<name>"),
+            (
+                r"(?<=x)(?<!y)[(\1][]1][[:digit:](]\(\\1\0\k<name>".to_string(),
+                0
+            )
+        );
+    }
+
+    #[test]
+    fn test_decoded() {
+        let decoded = Decoded::new(b"a\xffb");
+        assert_eq!(decoded.text, "a\u{fffd}b");
+        assert_eq!(decoded.range(1..4), 1..2);
+        assert_eq!(decoded.range(4..5), 2..3);
+        assert_eq!(decoded.position(2), 4);
+        assert_eq!(Decoded::new(b"ab").range(0..1), 0..1);
+    }
+}
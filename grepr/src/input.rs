@@ -0,0 +1,43 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    ops::Deref,
+    path::Path,
+};
+
+use memmap2::Mmap;
+
+/// The whole contents of a file searched with -U
+pub enum Contents {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Mapped(map) => map,
+            Self::Read(bytes) => bytes,
+        }
+    }
+}
+
+/// Maps a regular file into memory, or reads standard input or any other file to its end
+pub fn read_all(path: &Path) -> io::Result<Contents> {
+    let mut bytes = vec![];
+    if path.as_os_str() == "-" {
+        io::stdin().lock().read_to_end(&mut bytes)?;
+        return Ok(Contents::Read(bytes));
+    }
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if metadata.is_file() && metadata.len() > 0 {
+        // SAFETY: the map is only read. As with ripgrep, another process truncating the file
+        // while it's searched can still crash the search.
+        return Ok(Contents::Mapped(unsafe { Mmap::map(&file)? }));
+    }
+    file.read_to_end(&mut bytes)?;
+    Ok(Contents::Read(bytes))
+}
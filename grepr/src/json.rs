@@ -0,0 +1,150 @@
+use std::{
+    io::Write,
+    ops::{AddAssign, Range},
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    time::Duration,
+};
+
+use serde::{Serialize, Serializer};
+
+/// A line of `--json` output, modelled on ripgrep's
+#[derive(Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+pub enum Message<'a> {
+    /// Comes before the first match or context line of a file
+    Begin {
+        path: Data<'a>,
+    },
+    Match(Lines<'a>),
+    Context(Lines<'a>),
+    /// Comes after the last line of a file, and only if it had a begin message
+    End {
+        path: Data<'a>,
+        /// The offset of the NUL byte that made the file binary
+        binary_offset: Option<usize>,
+        stats: Stats,
+    },
+    /// The last message, totalling the stats of every file
+    Summary {
+        #[serde(serialize_with = "serialize_elapsed")]
+        elapsed_total: Duration,
+        stats: Stats,
+    },
+}
+
+impl Message<'_> {
+    pub fn write(&self, out: &mut impl Write) {
+        if serde_json::to_writer(&mut *out, self).is_ok() {
+            let _ = writeln!(out);
+        }
+    }
+}
+
+/// A selected or context line
+#[derive(Serialize)]
+pub struct Lines<'a> {
+    pub path: Data<'a>,
+    /// The line with its line ending
+    pub lines: Data<'a>,
+    pub line_number: usize,
+    /// The offset of the line's first byte in the file
+    pub absolute_offset: usize,
+    pub submatches: Vec<Submatch<'a>>,
+}
+
+/// A match in a line, with its byte range in the line
+#[derive(Serialize)]
+pub struct Submatch<'a> {
+    #[serde(rename = "match")]
+    pub matched: Data<'a>,
+    /// What replaces the match with --replace
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<Data<'a>>,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl<'a> Submatch<'a> {
+    pub fn new(line: &'a [u8], range: Range<usize>, replacement: Option<&'a [u8]>) -> Self {
+        Self {
+            matched: Data::new(&line[range.clone()]),
+            replacement: replacement.map(Data::new),
+            start: range.start,
+            end: range.end,
+        }
+    }
+}
+
+/// Text that's valid UTF-8, or else its base64-encoded bytes
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Data<'a> {
+    Text(&'a str),
+    Bytes(String),
+}
+
+impl<'a> Data<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        match str::from_utf8(bytes) {
+            Ok(text) => Self::Text(text),
+            Err(_) => Self::Bytes(utils::base64(bytes)),
+        }
+    }
+
+    pub fn from_path(path: &'a Path) -> Self {
+        Self::new(path.as_os_str().as_bytes())
+    }
+}
+
+/// What was searched and found, for a file or for all of them
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Stats {
+    #[serde(serialize_with = "serialize_elapsed")]
+    pub elapsed: Duration,
+    pub searches: usize,
+    pub searches_with_match: usize,
+    pub bytes_searched: usize,
+    pub matched_lines: usize,
+    pub matches: usize,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Self) {
+        self.elapsed += other.elapsed;
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.bytes_searched += other.bytes_searched;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+    }
+}
+
+/// Writes a duration as its seconds and nanoseconds, and a readable number of seconds
+fn serialize_elapsed<S: Serializer>(elapsed: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Elapsed {
+        secs: u64,
+        nanos: u32,
+        human: String,
+    }
+
+    Elapsed {
+        secs: elapsed.as_secs(),
+        nanos: elapsed.subsec_nanos(),
+        human: format!("{:.6}s", elapsed.as_secs_f64()),
+    }
+    .serialize(serializer)
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_data() {
+        let json = |bytes: &[u8]| serde_json::to_string(&Data::new(bytes)).unwrap();
+        assert_eq!(json(b"caf\xc3\xa9"), r#"{"text":"café"}"#);
+        assert_eq!(json(b"caf\xe9"), r#"{"bytes":"Y2Fm6Q=="}"#);
+    }
+}
@@ -1,22 +1,94 @@
 use std::{
-    io::BufRead,
+    io::{self, BufRead},
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
 };
 
-use clap::Parser;
-use regex::{Regex, RegexBuilder};
+use clap::{Parser, ValueEnum};
+use ignore::WalkState;
 use snafu::{ResultExt, Snafu};
-use utils::LinesWithEol;
-use walkdir::WalkDir;
+
+use color::ColorChoice;
+use matcher::{MatchError, MatchOptions, Matcher, Syntax};
+use posix::Posix;
+use printer::{Buffered, Printer};
+use walk::Filter;
+
+mod color;
+mod fancy;
+mod input;
+mod json;
+mod matcher;
+mod posix;
+mod printer;
+mod replace;
+mod walk;
 
 #[derive(Parser)]
 #[command(version, about)]
 struct Cli {
-    /// Pattern
-    pattern: String,
-    /// Input paths
-    #[arg(default_value = "-")]
+    /// Pattern, unless given with -e or -f
+    #[arg(required_unless_present_any = ["regexp", "file"])]
+    pattern: Option<String>,
+    /// Input paths [default: -]
     paths: Vec<PathBuf>,
+    /// Match PATTERN, which may be given more than once to match any of them
+    #[arg(
+        short = 'e',
+        long = "regexp",
+        value_name = "PATTERN",
+        allow_hyphen_values = true
+    )]
+    regexp: Vec<String>,
+    /// Match any of the patterns in FILE, one per line
+    #[arg(short = 'f', long = "file", value_name = "FILE")]
+    file: Vec<PathBuf>,
+    /// Treat the patterns as literal strings rather than regular expressions
+    #[arg(short = 'F', long = "fixed-strings", conflicts_with_all = ["basic_regexp", "extended_regexp", "pcre"])]
+    fixed_strings: bool,
+    /// Treat the patterns as POSIX basic regular expressions, as grep does by default, where
+    /// \(, \), \{, \}, \|, \+ and \? are operators and \1 to \9 refer back to groups
+    #[arg(short = 'G', long = "basic-regexp", conflicts_with_all = ["extended_regexp", "pcre"])]
+    basic_regexp: bool,
+    /// Treat the patterns as POSIX extended regular expressions, which may refer back to
+    /// groups with \1 to \9
+    #[arg(short = 'E', long = "extended-regexp", conflicts_with = "pcre")]
+    extended_regexp: bool,
+    /// Treat the patterns as Perl-compatible regular expressions, with backreferences and
+    /// lookaround. Files with a line that takes too many steps to search are given up on.
+    #[arg(short = 'P', long = "pcre", visible_alias = "perl-regexp")]
+    pcre: bool,
+    /// Only match whole words, which aren't next to letters, digits or underscores
+    #[arg(short = 'w', long = "word-regexp")]
+    word_regexp: bool,
+    /// Only match whole lines
+    #[arg(short = 'x', long = "line-regexp")]
+    line_regexp: bool,
+    /// Search each file as a whole, so that patterns can match across lines, and print every
+    /// line that a match spans
+    #[arg(short = 'U', long = "multiline")]
+    multiline: bool,
+    /// Lines of input and output end with NUL instead of a newline
+    #[arg(short = 'z', long = "null-data")]
+    null_data: bool,
+    /// Follow file names with NUL instead of the usual `:` or newline, as for `xargs -0`
+    #[arg(short = 'Z', long = "null")]
+    null: bool,
+    /// Print each match replaced with TEMPLATE, where $1 or ${name} stand for its groups and
+    /// $$ for a dollar sign
+    #[arg(long = "replace", value_name = "TEMPLATE", allow_hyphen_values = true)]
+    replace: Option<String>,
+    /// Write the replacements into the files instead, printing nothing but what -l or -c ask
+    /// for. Files with NUL bytes in their first block are left alone unless -a is given.
+    #[arg(long = "in-place", requires = "replace", conflicts_with_all = ["invert_match", "json"])]
+    in_place: bool,
+    /// Keep the original of each file edited with --in-place as FILE.bak
+    #[arg(long = "backup", requires = "in_place")]
+    backup: bool,
     /// Output lines that don't match the pattern
     #[arg(short = 'v', long = "invert-match")]
     invert_match: bool,
@@ -26,108 +98,321 @@ struct Cli {
     /// Output the number of lines matched
     #[arg(short = 'c', long = "count")]
     count: bool,
-    /// Match pattern in given directories recursively
+    /// Only print the names of files with selected lines
+    #[arg(
+        short = 'l',
+        long = "files-with-matches",
+        conflicts_with_all = ["files_without_match", "count"]
+    )]
+    files_with_matches: bool,
+    /// Only print the names of files without selected lines
+    #[arg(short = 'L', long = "files-without-match", conflicts_with = "count")]
+    files_without_match: bool,
+    /// Print nothing, and exit with status 0 as soon as a line is selected
+    #[arg(short = 'q', long = "quiet", visible_alias = "silent")]
+    quiet: bool,
+    /// How to search files with NUL bytes in their first block
+    #[arg(
+        long = "binary-files",
+        value_name = "TYPE",
+        value_enum,
+        default_value_t = BinaryFiles::Binary
+    )]
+    binary_files: BinaryFiles,
+    /// Search binary files as if they were text, same as --binary-files=text
+    #[arg(short = 'a', long = "text")]
+    text: bool,
+    /// Skip binary files, same as --binary-files=without-match
+    #[arg(short = 'I')]
+    skip_binary: bool,
+    /// Stop reading a file after NUM selected lines, printing their trailing context
+    #[arg(short = 'm', long = "max-count", value_name = "NUM")]
+    max_count: Option<usize>,
+    /// Match pattern in given directories recursively, skipping hidden files and the ones
+    /// left out by .gitignore, .ignore and .git/info/exclude
     #[arg(short = 'r', long = "recursive")]
     recursive: bool,
+    /// Search hidden files and directories with -r
+    #[arg(long = "hidden")]
+    hidden: bool,
+    /// Search ignored files and .git directories with -r
+    #[arg(long = "no-ignore")]
+    no_ignore: bool,
+    /// Only search files whose names match GLOB with -r, which may be given more than once
+    #[arg(long = "include", value_name = "GLOB")]
+    include: Vec<String>,
+    /// Skip files whose names match GLOB with -r
+    #[arg(long = "exclude", value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Skip directories whose names match GLOB with -r
+    #[arg(long = "exclude-dir", value_name = "GLOB")]
+    exclude_dir: Vec<String>,
+    /// Search directories with -r on N threads, or one per CPU if N is 0
+    #[arg(short = 'j', long = "threads", value_name = "N", default_value_t = 0)]
+    threads: usize,
+    /// Print the files found with -r in order of KEY, rather than as they are searched
+    #[arg(long = "sort", value_name = "KEY", value_enum)]
+    sort: Option<SortKey>,
+    /// Print NUM lines of context after each matching line
+    #[arg(short = 'A', long = "after-context", value_name = "NUM")]
+    after_context: Option<usize>,
+    /// Print NUM lines of context before each matching line
+    #[arg(short = 'B', long = "before-context", value_name = "NUM")]
+    before_context: Option<usize>,
+    /// Print NUM lines of context before and after each matching line
+    #[arg(short = 'C', long = "context", value_name = "NUM")]
+    context: Option<usize>,
+    /// Prefix each line with its line number, counted from 1
+    #[arg(short = 'n', long = "line-number")]
+    line_number: bool,
+    /// Prefix each line with the offset of its first byte in the file, or of the match with -o
+    #[arg(short = 'b', long = "byte-offset")]
+    byte_offset: bool,
+    /// Print each match on its own line instead of the lines holding them
+    #[arg(short = 'o', long = "only-matching")]
+    only_matching: bool,
+    /// Print a JSON object per line for the start and end of each file with selected lines,
+    /// each selected and context line, and a summary at the end
+    #[arg(
+        long = "json",
+        conflicts_with_all = ["count", "files_with_matches", "files_without_match", "only_matching"]
+    )]
+    json: bool,
+    /// Highlight matches, file names and prefixes with the colours in GREP_COLORS
+    #[arg(
+        long = "color",
+        visible_alias = "colour",
+        value_name = "WHEN",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = ColorChoice::Auto,
+        default_missing_value = "auto"
+    )]
+    color: ColorChoice,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum BinaryFiles {
+    /// Print "Binary file X matches" instead of the selected lines
+    Binary,
+    /// Print the selected lines as they are
+    Text,
+    /// Treat binary files as if nothing matched
+    WithoutMatch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SortKey {
+    Path,
 }
 
 #[derive(Snafu, Debug)]
 pub enum CliError {
-    Io {
-        source: std::io::Error,
-    },
     #[snafu(display("{}: {}", path.display(), source))]
     IoPath {
         source: std::io::Error,
         path: PathBuf,
     },
-    Walkdir {
-        source: walkdir::Error,
-    },
+    #[snafu(display("{}", source))]
+    Walk { source: ignore::Error },
+    #[snafu(display("{}", source))]
+    Glob { source: globset::Error },
+    #[snafu(display("{} is a directory", path.display()))]
+    IsDirectory { path: PathBuf },
+    #[snafu(display("Standard input can't be edited in place"))]
+    InPlaceStdin,
     #[snafu(display("Invalid pattern \"{}\"", pattern))]
     Regex {
         source: regex::Error,
         pattern: String,
     },
+    #[snafu(display("Invalid pattern \"{}\"", pattern))]
+    FancyRegex {
+        source: fancy_regex::Error,
+        pattern: String,
+    },
+    #[snafu(display("{}: {}", path.display(), source))]
+    Match { source: MatchError, path: PathBuf },
+    #[snafu(display("{}", source))]
+    AhoCorasick { source: aho_corasick::BuildError },
 }
 
 pub type CliResult<T> = std::result::Result<T, CliError>;
 
-fn print_file_matches<P: AsRef<Path>, B: BufRead>(
-    path: P,
-    buffer: B,
-    pattern: &Regex,
-    invert_match: bool,
-    print_filename: bool,
-    print_count: bool,
-) -> CliResult<()> {
-    let mut match_count: usize = 0;
-    for line in buffer.lines_with_eol() {
-        let line = line.context(IoSnafu {})?;
-        if !invert_match && !pattern.is_match(&line) {
-            continue;
-        }
-        if !print_count {
-            if print_filename {
-                print!("{}:", path.as_ref().display());
+/// Whether any line was selected, and whether any file couldn't be searched
+#[derive(Default)]
+struct Status {
+    matched: bool,
+    failed: bool,
+}
+
+impl Status {
+    fn record(&mut self, result: CliResult<usize>) {
+        match result {
+            Ok(count) => self.matched |= count > 0,
+            Err(err) => {
+                eprintln!("{err}");
+                self.failed = true;
             }
-            print!("{line}");
-        }
-        match_count += 1;
-    }
-    if print_count {
-        if print_filename {
-            print!("{}:", path.as_ref().display());
         }
-        println!("{match_count}");
     }
+}
 
-    Ok(())
+/// What every path is searched with
+struct Search {
+    cli: Cli,
+    matcher: Matcher,
+    filter: Filter,
+    threads: usize,
 }
 
-pub fn run() -> CliResult<()> {
-    let cli = Cli::parse();
-    let pattern = RegexBuilder::new(&cli.pattern)
-        .case_insensitive(cli.ignore_case)
-        .build()
-        .context(RegexSnafu {
-            pattern: cli.pattern,
-        })?;
-
-    for path in cli.paths.iter() {
-        if cli.recursive && path.is_dir() {
-            let walker = WalkDir::new(path);
-            for entry in walker {
-                let entry = entry.context(WalkdirSnafu {})?;
-                if entry.file_type().is_file() {
-                    let path = entry.path();
-                    let buffer = utils::reader_from_path(path).context(IoPathSnafu { path })?;
-                    print_file_matches(path, buffer, &pattern, cli.invert_match, true, cli.count)?;
+impl Search {
+    fn search_file(
+        &self,
+        printer: &mut Printer,
+        path: &Path,
+        print_filename: bool,
+    ) -> CliResult<usize> {
+        let mut stdout = io::stdout().lock();
+        printer.print_file_matches(&mut stdout, path, &self.matcher, print_filename)
+    }
+
+    /// Searches the files below `root` on several threads. Each file's output is printed in
+    /// one piece as soon as it's searched, or after all of them in order with --sort.
+    fn search_dir(&self, printer: &mut Printer, root: &Path, status: &mut Status) {
+        let template = printer.clone();
+        let quit = AtomicBool::new(false);
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let walker = walk::walker(root, &self.cli, &self.filter, self.threads);
+                walker.run(|| {
+                    let sender = sender.clone();
+                    let (template, quit) = (&template, &quit);
+                    Box::new(move |entry| {
+                        // -q is done at the first selected line
+                        if quit.load(Ordering::Relaxed) {
+                            return WalkState::Quit;
+                        }
+                        let result = match entry {
+                            Ok(entry) if entry.file_type().is_some_and(|t| t.is_file()) => {
+                                template.search_buffered(entry.path(), &self.matcher)
+                            }
+                            Ok(_) => return WalkState::Continue,
+                            Err(source) => Err(CliError::Walk { source }),
+                        };
+                        if self.cli.quiet && result.as_ref().is_ok_and(|b| b.match_count > 0) {
+                            quit.store(true, Ordering::Relaxed);
+                        }
+                        let _ = sender.send(result);
+                        WalkState::Continue
+                    })
+                });
+                drop(sender);
+            });
+
+            let mut stdout = io::stdout().lock();
+            let mut found: Vec<Buffered> = vec![];
+            for result in receiver {
+                match result {
+                    Ok(buffered) if self.cli.sort.is_some() => found.push(buffered),
+                    Ok(buffered) => {
+                        printer.print_buffered(&mut stdout, &buffered);
+                        status.record(Ok(buffered.match_count));
+                    }
+                    Err(err) => status.record(Err(err)),
                 }
             }
-        } else {
-            if path.is_dir() {
-                eprintln!("{} is a directory", path.display());
-                continue;
-            }
-            match utils::reader_from_path(path).context(IoPathSnafu { path }) {
-                Ok(buffer) => {
-                    print_file_matches(
-                        path,
-                        buffer,
-                        &pattern,
-                        cli.invert_match,
-                        cli.paths.len() > 1,
-                        cli.count,
-                    )?;
-                }
-                Err(err) => {
-                    eprintln!("{}", err);
-                    continue;
-                }
+            found.sort_by(|a, b| a.path.cmp(&b.path));
+            for buffered in found {
+                printer.print_buffered(&mut stdout, &buffered);
+                status.record(Ok(buffered.match_count));
             }
+        });
+    }
+}
+
+/// Returns the exit status: 0 if any line was selected, 1 if none were, and 2 if any file
+/// couldn't be searched
+pub fn run() -> CliResult<i32> {
+    let mut cli = Cli::parse();
+    let mut printer = Printer::new(&cli);
+    let mut patterns = cli.regexp.clone();
+    for path in &cli.file {
+        let buffer = utils::reader_from_path(path).context(IoPathSnafu { path })?;
+        for line in buffer.lines() {
+            patterns.push(line.context(IoPathSnafu { path })?);
+        }
+    }
+    // With -e or -f, the first positional argument is a path
+    match cli.pattern.take() {
+        Some(pattern) if patterns.is_empty() => patterns.push(pattern),
+        Some(path) => cli.paths.insert(0, path.into()),
+        None => {}
+    }
+    if cli.paths.is_empty() {
+        cli.paths.push(PathBuf::from("-"));
+    }
+    if cli.in_place && cli.paths.iter().any(|path| path.as_os_str() == "-") {
+        return Err(CliError::InPlaceStdin);
+    }
+    let matcher = Matcher::new(
+        &patterns,
+        MatchOptions {
+            syntax: if cli.fixed_strings {
+                Syntax::Fixed
+            } else if cli.basic_regexp {
+                Syntax::Posix(Posix::Basic)
+            } else if cli.extended_regexp {
+                Syntax::Posix(Posix::Extended)
+            } else if cli.pcre {
+                Syntax::Pcre
+            } else {
+                Syntax::Regex
+            },
+            ignore_case: cli.ignore_case,
+            word: cli.word_regexp,
+            line: cli.line_regexp,
+            multi_line: cli.multiline,
+            line_terminator: if cli.null_data { b'\0' } else { b'\n' },
+        },
+    )?;
+    let threads = match cli.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let search = Search {
+        filter: Filter::new(&cli)?,
+        matcher,
+        threads,
+        cli,
+    };
+
+    let print_filename = search.cli.paths.len() > 1;
+    let mut status = Status::default();
+    for path in &search.cli.paths {
+        if search.cli.recursive && path.is_dir() {
+            search.search_dir(&mut printer, path, &mut status);
+        } else if path.is_dir() {
+            status.record(Err(CliError::IsDirectory { path: path.clone() }));
+        } else {
+            status.record(search.search_file(&mut printer, path, print_filename));
+        }
+        // -q is done at the first selected line
+        if search.cli.quiet && status.matched {
+            break;
         }
     }
+    printer.finish(&mut io::stdout().lock());
 
-    Ok(())
+    Ok(if search.cli.quiet && status.matched {
+        0
+    } else if status.failed {
+        2
+    } else if status.matched {
+        0
+    } else {
+        1
+    })
 }
@@ -1,8 +1,11 @@
 use std::process;
 
 fn main() {
-    if let Err(error) = grepr::run() {
-        eprintln!("{error}");
-        process::exit(1)
+    match grepr::run() {
+        Ok(status) => process::exit(status),
+        Err(error) => {
+            eprintln!("{error}");
+            process::exit(2)
+        }
     }
 }
@@ -0,0 +1,517 @@
+use std::{cmp::Reverse, fmt, ops::Range};
+
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind};
+use regex::bytes::{Regex, RegexBuilder};
+use snafu::ResultExt;
+
+use crate::{
+    AhoCorasickSnafu, CliResult, RegexSnafu,
+    fancy::FancyEngine,
+    posix::{self, Posix, Translated},
+    replace::Template,
+};
+
+/// The syntax of the patterns, which decides the engine that matches them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Syntax {
+    /// The syntax of the `regex` crate
+    #[default]
+    Regex,
+    /// Literal strings (`-F`)
+    Fixed,
+    /// POSIX basic or extended regular expressions (`-G`, `-E`), which are rewritten for the
+    /// `regex` crate unless they have backreferences
+    Posix(Posix),
+    /// Perl-compatible regular expressions with backreferences and lookaround (`-P`)
+    Pcre,
+}
+
+/// How the patterns are matched
+#[derive(Debug, Clone, Copy)]
+pub struct MatchOptions {
+    pub syntax: Syntax,
+    pub ignore_case: bool,
+    /// Matches must start and end at word boundaries (`-w`)
+    pub word: bool,
+    /// Matches must span the whole line (`-x`)
+    pub line: bool,
+    /// The text has several lines, which `^` and `$` match at the start and end of (`-U`)
+    pub multi_line: bool,
+    /// The byte that ends lines, which `.` doesn't match
+    pub line_terminator: u8,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            syntax: Syntax::default(),
+            ignore_case: false,
+            word: false,
+            line: false,
+            multi_line: false,
+            line_terminator: b'\n',
+        }
+    }
+}
+
+/// Why an engine couldn't finish a search, such as running out of backtracking steps
+#[derive(Debug)]
+pub struct MatchError(pub String);
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for MatchError {}
+
+pub type MatchResult<T> = Result<T, MatchError>;
+
+/// Finds any of several patterns in the bytes of a line, which is given without its line ending,
+/// or of several lines with `-U`
+pub trait Engine: Send + Sync {
+    fn is_match(&self, text: &[u8]) -> MatchResult<bool> {
+        Ok(!self.find_iter(text)?.is_empty())
+    }
+
+    /// Returns the leftmost matches that don't overlap, in order
+    fn find_iter(&self, text: &[u8]) -> MatchResult<Vec<Range<usize>>>;
+
+    /// Returns what replaces a match found by `find_iter` in `text`
+    fn replacement(
+        &self,
+        text: &[u8],
+        found: Range<usize>,
+        template: &Template,
+    ) -> MatchResult<Vec<u8>>;
+}
+
+/// The engine that the syntax of the patterns calls for
+pub struct Matcher(Box<dyn Engine>);
+
+impl Matcher {
+    pub fn new(patterns: &[String], options: MatchOptions) -> CliResult<Self> {
+        let engine: Box<dyn Engine> = match options.syntax {
+            // Aho-Corasick only folds the case of ASCII letters
+            Syntax::Fixed
+                if !(options.ignore_case && patterns.iter().any(|pattern| !pattern.is_ascii())) =>
+            {
+                Box::new(LiteralEngine::new(patterns, options)?)
+            }
+            Syntax::Fixed => {
+                // Longer strings come first, so the leftmost match is also the longest as
+                // with Aho-Corasick
+                let mut patterns = patterns.to_vec();
+                patterns.sort_by_key(|pattern| Reverse(pattern.len()));
+                let escaped: Vec<String> = patterns
+                    .iter()
+                    .map(|pattern| regex::escape(pattern))
+                    .collect();
+                Box::new(RegexEngine::new(&escaped, &patterns, options)?)
+            }
+            Syntax::Regex => Box::new(RegexEngine::new(patterns, patterns, options)?),
+            Syntax::Posix(posix) => {
+                let translated: Vec<Translated> = patterns
+                    .iter()
+                    .map(|pattern| posix::translate(pattern, posix))
+                    .collect();
+                let backreferences = translated.iter().any(|t| t.backreferences);
+                let translated: Vec<String> = translated.into_iter().map(|t| t.pattern).collect();
+                match backreferences {
+                    true => Box::new(FancyEngine::new(&translated, patterns, options)?),
+                    false => Box::new(RegexEngine::new(&translated, patterns, options)?),
+                }
+            }
+            Syntax::Pcre => Box::new(FancyEngine::new(patterns, patterns, options)?),
+        };
+        Ok(Self(engine))
+    }
+
+    pub fn is_match(&self, text: &[u8]) -> MatchResult<bool> {
+        self.0.is_match(text)
+    }
+
+    pub fn find_iter(&self, text: &[u8]) -> MatchResult<Vec<Range<usize>>> {
+        self.0.find_iter(text)
+    }
+
+    pub fn replacement(
+        &self,
+        text: &[u8],
+        found: Range<usize>,
+        template: &Template,
+    ) -> MatchResult<Vec<u8>> {
+        self.0.replacement(text, found, template)
+    }
+}
+
+/// Joins patterns into one that matches any of them, or nothing if there are none
+pub fn alternation(patterns: &[String]) -> String {
+    match patterns.is_empty() {
+        true => r"[^\s\S]".to_string(),
+        false => patterns
+            .iter()
+            .map(|pattern| format!("(?:{pattern})"))
+            .collect::<Vec<_>>()
+            .join("|"),
+    }
+}
+
+/// Returns the first of `originals` whose rewrite in `patterns` is invalid on its own, to be
+/// blamed for the error in their alternation
+pub fn invalid_pattern(
+    patterns: &[String],
+    originals: &[String],
+    valid: impl Fn(&str) -> bool,
+) -> String {
+    patterns
+        .iter()
+        .zip(originals)
+        .find(|(pattern, _)| !valid(pattern))
+        .map_or_else(|| originals.join("\n"), |(_, original)| original.clone())
+}
+
+/// Matches regexes with the `regex` crate
+struct RegexEngine {
+    regex: Regex,
+    /// The regex is wrapped in non-word characters, and the match is its first group
+    word: bool,
+}
+
+impl RegexEngine {
+    /// Builds the alternation of `patterns`, which are rewrites of `originals`
+    fn new(patterns: &[String], originals: &[String], options: MatchOptions) -> CliResult<Self> {
+        let pattern = alternation(patterns);
+        let (pattern, word) = if options.line {
+            (format!("^(?:{pattern})$"), false)
+        } else if options.word {
+            (format!(r"(?:^|\W)({pattern})(?:\W|$)"), true)
+        } else {
+            (pattern, false)
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(options.ignore_case)
+            .multi_line(options.multi_line)
+            .line_terminator(options.line_terminator)
+            .build()
+            .with_context(|_| RegexSnafu {
+                pattern: invalid_pattern(patterns, originals, |pattern| {
+                    Regex::new(pattern).is_ok()
+                }),
+            })?;
+        Ok(Self { regex, word })
+    }
+}
+
+impl Engine for RegexEngine {
+    fn is_match(&self, text: &[u8]) -> MatchResult<bool> {
+        Ok(self.regex.is_match(text))
+    }
+
+    fn find_iter(&self, text: &[u8]) -> MatchResult<Vec<Range<usize>>> {
+        if !self.word {
+            return Ok(self
+                .regex
+                .find_iter(text)
+                .map(|found| found.range())
+                .collect());
+        }
+        let mut matches = vec![];
+        let mut start = 0;
+        // The non-word character before a match may end the previous one
+        while start <= text.len()
+            && let Some(found) = self
+                .regex
+                .captures_at(text, start)
+                .and_then(|caps| caps.get(1))
+        {
+            matches.push(found.range());
+            start = match found.is_empty() {
+                true => found.end() + 1,
+                false => found.end(),
+            };
+        }
+        Ok(matches)
+    }
+
+    fn replacement(
+        &self,
+        text: &[u8],
+        found: Range<usize>,
+        template: &Template,
+    ) -> MatchResult<Vec<u8>> {
+        // With -w the match is the first group, after the character before it
+        let (start, shift) = match self.word {
+            true => (prev_char_start(text, found.start), 1),
+            false => (found.start, 0),
+        };
+        Ok(match self.regex.captures_at(text, start) {
+            Some(caps) if caps.get(shift).is_some_and(|group| group.range() == found) => template
+                .expand(
+                    |i| caps.get(i + shift).map(|group| group.as_bytes()),
+                    |name| caps.name(name).map(|group| group.as_bytes()),
+                ),
+            _ => whole_match(text, found, template),
+        })
+    }
+}
+
+/// Matches fixed strings, all searched for at once
+struct LiteralEngine {
+    automaton: AhoCorasick,
+    word: bool,
+    line: bool,
+    /// The terminator of the lines in the text with `-U`
+    multi_line: Option<u8>,
+}
+
+impl LiteralEngine {
+    fn new(patterns: &[String], options: MatchOptions) -> CliResult<Self> {
+        // Overlapping matches are needed to find the ones at word or line boundaries
+        let match_kind = if options.word || options.line {
+            MatchKind::Standard
+        } else {
+            MatchKind::LeftmostLongest
+        };
+        let automaton = AhoCorasickBuilder::new()
+            .ascii_case_insensitive(options.ignore_case)
+            .match_kind(match_kind)
+            .build(patterns)
+            .context(AhoCorasickSnafu)?;
+        Ok(Self {
+            automaton,
+            word: options.word && !options.line,
+            line: options.line,
+            multi_line: options.multi_line.then_some(options.line_terminator),
+        })
+    }
+}
+
+impl Engine for LiteralEngine {
+    fn is_match(&self, text: &[u8]) -> MatchResult<bool> {
+        match self.word || self.line {
+            true => Ok(!self.find_iter(text)?.is_empty()),
+            false => Ok(self.automaton.is_match(text)),
+        }
+    }
+
+    fn find_iter(&self, text: &[u8]) -> MatchResult<Vec<Range<usize>>> {
+        if !(self.word || self.line) {
+            return Ok(self
+                .automaton
+                .find_iter(text)
+                .map(|found| found.range())
+                .collect());
+        }
+        let line_start = |i: usize| i == 0 || Some(text[i - 1]) == self.multi_line;
+        let line_end = |i: usize| i == text.len() || Some(text[i]) == self.multi_line;
+        let mut candidates: Vec<Range<usize>> = self
+            .automaton
+            .find_overlapping_iter(text)
+            .map(|found| found.range())
+            .filter(|range| match self.line {
+                true => line_start(range.start) && line_end(range.end),
+                false => {
+                    !char_before(text, range.start).is_some_and(is_word_char)
+                        && !char_after(text, range.end).is_some_and(is_word_char)
+                }
+            })
+            .collect();
+        candidates.sort_by_key(|range| (range.start, usize::MAX - range.end));
+        let mut matches: Vec<Range<usize>> = vec![];
+        for range in candidates {
+            if matches
+                .last()
+                .is_none_or(|last| last.end <= range.start && last.start != range.start)
+            {
+                matches.push(range);
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Fixed strings only have the whole match, `$0`
+    fn replacement(
+        &self,
+        text: &[u8],
+        found: Range<usize>,
+        template: &Template,
+    ) -> MatchResult<Vec<u8>> {
+        Ok(whole_match(text, found, template))
+    }
+}
+
+/// Expands a template with only the whole match, `$0`, for when there are no groups
+pub fn whole_match(text: &[u8], found: Range<usize>, template: &Template) -> Vec<u8> {
+    let whole = &text[found];
+    template.expand(|i| (i == 0).then_some(whole), |_| None)
+}
+
+/// Returns the start of the UTF-8 character before `i`, or of the byte before it if it's
+/// invalid
+fn prev_char_start(text: &[u8], i: usize) -> usize {
+    match char_before(text, i) {
+        Some(c) => i - c.len_utf8(),
+        None => i.saturating_sub(1),
+    }
+}
+
+/// Returns whether `c` is a letter, digit or underscore, which can't be next to a match with -w
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Decodes the UTF-8 character that ends at `i`, if it's valid
+fn char_before(text: &[u8], i: usize) -> Option<char> {
+    // A character has up to three continuation bytes after its first byte
+    let start = (i.saturating_sub(4)..i)
+        .rev()
+        .find(|&j| text[j] & 0b1100_0000 != 0b1000_0000)?;
+    str::from_utf8(&text[start..i]).ok()?.chars().next()
+}
+
+/// Decodes the UTF-8 character that starts at `i`, if it's valid
+fn char_after(text: &[u8], i: usize) -> Option<char> {
+    text[i..].utf8_chunks().next()?.valid().chars().next()
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn find(patterns: &[&str], options: MatchOptions, text: &str) -> Vec<Range<usize>> {
+        let patterns: Vec<String> = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        let fixed = Matcher::new(
+            &patterns,
+            MatchOptions {
+                syntax: Syntax::Fixed,
+                ..options
+            },
+        )
+        .unwrap();
+        let mut escaped = patterns.clone();
+        escaped.sort_by_key(|pattern| Reverse(pattern.len()));
+        let escaped: Vec<String> = escaped
+            .iter()
+            .map(|pattern| regex::escape(pattern))
+            .collect();
+        let regex = Matcher::new(&escaped, options).unwrap();
+        // Both engines agree on literal patterns
+        let text = text.as_bytes();
+        let matches = fixed.find_iter(text).unwrap();
+        assert_eq!(regex.find_iter(text).unwrap(), matches);
+        assert_eq!(fixed.is_match(text).unwrap(), !matches.is_empty());
+        assert_eq!(regex.is_match(text).unwrap(), !matches.is_empty());
+        matches
+    }
+
+    #[test]
+    fn test_literal() {
+        let options = MatchOptions::default();
+        assert_eq!(
+            find(&["ab", "abc", "cd"], options, "abcd abd"),
+            vec![0..3, 5..7]
+        );
+        assert!(find(&["x"], options, "abc").is_empty());
+        assert!(find(&[], options, "abc").is_empty());
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let options = MatchOptions {
+            ignore_case: true,
+            ..Default::default()
+        };
+        assert_eq!(find(&["the"], options, "The theme"), vec![0..3, 4..7]);
+        // Non-ASCII patterns are folded too
+        assert_eq!(find(&["émile"], options, "ÉMILE Zola"), vec![0..6]);
+    }
+
+    #[test]
+    fn test_word() {
+        let options = MatchOptions {
+            word: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            find(&["the"], options, "the theme, the_end the"),
+            vec![0..3, 19..22]
+        );
+        assert_eq!(find(&["foo", "foo bar"], options, "foo bar"), vec![0..7]);
+        // A longer match that isn't a word leaves room for a shorter one
+        assert_eq!(find(&["ab", "abc"], options, "abcd ab"), vec![5..7]);
+        assert_eq!(find(&["a"], options, "a a a"), vec![0..1, 2..3, 4..5]);
+        assert_eq!(find(&["!"], options, "a!b !"), vec![4..5]);
+        // Letters outside ASCII are part of words too
+        assert!(find(&["mile"], options, "émile").is_empty());
+        assert_eq!(find(&["zola"], options, "émile zola"), vec![7..11]);
+    }
+
+    #[test]
+    fn test_line() {
+        let options = MatchOptions {
+            line: true,
+            word: true,
+            ..Default::default()
+        };
+        assert_eq!(find(&["fox", "the fox"], options, "the fox"), vec![0..7]);
+        assert!(find(&["fox"], options, "the fox").is_empty());
+        assert_eq!(find(&[""], options, ""), vec![0..0]);
+    }
+
+    #[test]
+    fn test_multi_line() {
+        let options = MatchOptions {
+            line: true,
+            multi_line: true,
+            ..Default::default()
+        };
+        assert_eq!(find(&["b", "c"], options, "a\nb\nbc\nc"), vec![2..3, 7..8]);
+        let options = MatchOptions {
+            line: true,
+            multi_line: true,
+            line_terminator: b'\0',
+            ..Default::default()
+        };
+        assert_eq!(find(&["b"], options, "b\nb\0b"), vec![4..5]);
+    }
+
+    #[test]
+    fn test_replacement() {
+        let replace = |patterns: &[&str], options: MatchOptions, text: &str, template: &str| {
+            let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+            let matcher = Matcher::new(&patterns, options).unwrap();
+            let text = text.as_bytes();
+            let template = Template::parse(template);
+            let replaced: Vec<String> = matcher
+                .find_iter(text)
+                .unwrap()
+                .into_iter()
+                .map(|found| {
+                    String::from_utf8(matcher.replacement(text, found, &template).unwrap()).unwrap()
+                })
+                .collect();
+            replaced
+        };
+        let options = MatchOptions::default();
+        assert_eq!(
+            replace(&[r"(\w+)@(?P<host>\w+)"], options, "a@b c@d", "$host:$1"),
+            ["b:a", "d:c"]
+        );
+        let word = MatchOptions {
+            word: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            replace(&[r"(\w)(\d)"], word, "a1 b2 cc3 é4", "$2$1"),
+            ["1a", "2b", "4é"]
+        );
+        assert_eq!(replace(&["é(.)"], word, "é1 xé2", "[$0|$1]"), ["[é1|1]"]);
+        let fixed = MatchOptions {
+            syntax: Syntax::Fixed,
+            ..Default::default()
+        };
+        assert_eq!(replace(&["a.b"], fixed, "a.b", "<$0$1>"), ["<a.b>"]);
+    }
+}
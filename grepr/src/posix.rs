@@ -0,0 +1,223 @@
+/// The POSIX syntaxes that grep patterns are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Posix {
+    /// `-G`, where groups, alternation, intervals, `+` and `?` are written with a backslash
+    Basic,
+    /// `-E`
+    Extended,
+}
+
+/// A POSIX pattern rewritten in the syntax of the `regex` crate
+#[derive(Debug, PartialEq)]
+pub struct Translated {
+    pub pattern: String,
+    /// Whether it refers back to groups, which only a backtracking engine can match
+    pub backreferences: bool,
+}
+
+/// Rewrites a POSIX pattern, with its backreferences written as `\k<N>`
+pub fn translate(pattern: &str, syntax: Posix) -> Translated {
+    let basic = syntax == Posix::Basic;
+    let mut out = String::with_capacity(pattern.len());
+    let mut backreferences = false;
+    // A repetition operator with nothing before it to repeat is literal, as `^` is anywhere
+    // else in a BRE
+    let mut at_start = true;
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        let escaped = c == '\\' && !rest.is_empty();
+        let c = match escaped {
+            true => {
+                let next = rest.chars().next().unwrap_or_default();
+                rest = &rest[next.len_utf8()..];
+                next
+            }
+            false => c,
+        };
+        // Operators are written with a backslash in BREs, and without one in EREs
+        let operator = escaped == basic;
+        match c {
+            '(' if operator => {
+                out.push('(');
+                at_start = true;
+                continue;
+            }
+            '|' if operator => {
+                out.push('|');
+                at_start = true;
+                continue;
+            }
+            ')' if operator => out.push(')'),
+            '*' if !escaped && !at_start => out.push('*'),
+            '+' | '?' if operator && !at_start => out.push(c),
+            '{' if operator && !at_start => match interval(rest, basic) {
+                Some((interval, after)) => {
+                    out.push_str(&interval);
+                    rest = after;
+                }
+                None => out.push_str(r"\{"),
+            },
+            '^' if !escaped && (at_start || !basic) => {
+                out.push('^');
+                continue;
+            }
+            '$' if !escaped
+                && (!basic
+                    || rest.is_empty()
+                    || rest.starts_with(r"\)")
+                    || rest.starts_with(r"\|")) =>
+            {
+                out.push('$')
+            }
+            '1'..='9' if escaped => {
+                out.push_str(&format!(r"\k<{c}>"));
+                backreferences = true;
+            }
+            '<' | '>' | 'b' | 'B' | 'w' | 'W' | 's' | 'S' if escaped => {
+                out.push('\\');
+                out.push(c);
+            }
+            '`' if escaped => out.push_str(r"\A"),
+            '\'' if escaped => out.push_str(r"\z"),
+            '.' if !escaped => out.push('.'),
+            '[' if !escaped => match bracket(rest) {
+                Some((class, after)) => {
+                    out.push_str(&class);
+                    rest = after;
+                }
+                // An unclosed bracket is left for the regex to report
+                None => out.push('['),
+            },
+            // Anything else stands for itself, like an unknown escape in GNU grep
+            _ => out.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+        at_start = false;
+    }
+    Translated {
+        pattern: out,
+        backreferences,
+    }
+}
+
+/// Rewrites the interval at the start of `rest`, just after its opening brace, returning it
+/// and what follows it. An interval without a minimum starts at 0.
+fn interval(rest: &str, basic: bool) -> Option<(String, &str)> {
+    let close = if basic { r"\}" } else { "}" };
+    let end = rest.find(close)?;
+    let bounds = &rest[..end];
+    let (min, max) = match bounds.split_once(',') {
+        Some((min, max)) => (min, Some(max)),
+        None => (bounds, None),
+    };
+    let digits = |bound: &str| bound.chars().all(|c| c.is_ascii_digit());
+    if !digits(min) || !max.is_none_or(digits) || (min.is_empty() && max.is_none()) {
+        return None;
+    }
+    let min = if min.is_empty() { "0" } else { min };
+    let interval = match max {
+        Some(max) => format!("{{{min},{max}}}"),
+        None => format!("{{{min}}}"),
+    };
+    Some((interval, &rest[end + close.len()..]))
+}
+
+/// Rewrites the bracket expression at the start of `rest`, just after its `[`, returning it
+/// and what follows it, or `None` if it isn't closed. Backslashes are literal in it, and `]`
+/// is too if it comes first.
+fn bracket(mut rest: &str) -> Option<(String, &str)> {
+    let mut class = String::from("[");
+    if let Some(after) = rest.strip_prefix('^') {
+        class.push('^');
+        rest = after;
+    }
+    if let Some(after) = rest.strip_prefix(']') {
+        class.push_str(r"\]");
+        rest = after;
+    }
+    loop {
+        let mut chars = rest.chars();
+        match chars.next()? {
+            ']' => {
+                class.push(']');
+                return Some((class, chars.as_str()));
+            }
+            // Character classes like [:alpha:], and equivalence classes and collating
+            // symbols, which are only supported for single characters
+            '[' if matches!(chars.clone().next(), Some(':' | '=' | '.')) => {
+                let kind = chars.next()?;
+                let inner = chars.as_str();
+                let end = inner.find(&format!("{kind}]"))?;
+                let name = &inner[..end];
+                match kind {
+                    ':' => class.push_str(&format!("[:{name}:]")),
+                    _ => class.push_str(&regex::escape(name)),
+                }
+                rest = &inner[end + 2..];
+                continue;
+            }
+            // These are set operators or escapes inside the regex crate's classes
+            c @ ('[' | '\\' | '&' | '~') => {
+                class.push('\\');
+                class.push(c);
+            }
+            '-' if chars.as_str().starts_with('-') => class.push_str(r"\-"),
+            c => class.push(c),
+        }
+        rest = chars.as_str();
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn basic(pattern: &str) -> String {
+        translate(pattern, Posix::Basic).pattern
+    }
+
+    fn extended(pattern: &str) -> String {
+        translate(pattern, Posix::Extended).pattern
+    }
+
+    #[test]
+    fn test_basic() {
+        assert_eq!(basic(r"\(foo\)\{2\}"), "(foo){2}");
+        assert_eq!(basic(r"a\|b\+c\?"), "a|b+c?");
+        assert_eq!(basic("(a|b)+c?{1}"), r"\(a\|b\)\+c\?\{1\}");
+        assert_eq!(basic(r"*a\(*b\)"), r"\*a(\*b)");
+        assert_eq!(basic("^a^b$c$"), r"^a\^b\$c$");
+        assert_eq!(basic(r"\(^a$\)"), "(^a$)");
+        assert_eq!(basic(r"a\{,3\}b\{2,\}c\{x\}"), r"a{0,3}b{2,}c\{x\}");
+        assert_eq!(basic(r"\.\*\<a\>\w\y\"), r"\.\*\<a\>\wy\\");
+    }
+
+    #[test]
+    fn test_extended() {
+        assert_eq!(extended("(foo){2}|b+c?"), "(foo){2}|b+c?");
+        assert_eq!(extended(r"\(a\)\{"), r"\(a\)\{");
+        assert_eq!(extended("*a|+b"), r"\*a|\+b");
+        assert_eq!(extended("a{x}a^$"), r"a\{x\}a^$");
+    }
+
+    #[test]
+    fn test_bracket() {
+        assert_eq!(basic(r"[]a\]"), r"[\]a\\]");
+        assert_eq!(basic("[^[:alpha:]_-]"), "[^[:alpha:]_-]");
+        assert_eq!(basic("[[=e=][.-.]&&]"), r"[e\-\&\&]");
+        assert_eq!(basic("[a[b]"), r"[a\[b]");
+        assert_eq!(basic("[ab"), r"[ab");
+    }
+
+    #[test]
+    fn test_backreferences() {
+        assert_eq!(
+            translate(r"\(a\)\(b\)\2\1", Posix::Basic),
+            Translated {
+                pattern: r"(a)(b)\k<2>\k<1>".to_string(),
+                backreferences: true,
+            }
+        );
+        assert!(!translate("(a)", Posix::Extended).backreferences);
+    }
+}
@@ -0,0 +1,685 @@
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    env,
+    io::{self, BufRead, IsTerminal, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use ansi_term::Style;
+use snafu::ResultExt;
+
+use crate::{
+    BinaryFiles, Cli, CliResult, IoPathSnafu, MatchSnafu,
+    color::{ColorChoice, Colors},
+    input,
+    json::{self, Data, Message, Stats, Submatch},
+    matcher::{MatchResult, Matcher},
+    replace::{self, Template},
+};
+
+/// The size of the first block of a file, which is checked for binary data
+const BLOCK_SIZE: usize = 8 * 1024;
+
+/// A line read from a file, with its position
+struct Line {
+    /// Counted from 1
+    number: usize,
+    /// The offset of the line's first byte in the file
+    offset: usize,
+    text: Vec<u8>,
+    /// Whether the text ends with the line terminator
+    terminated: bool,
+    /// The parts of the line that matches spanning several lines cover with -U
+    matches: Option<Vec<Range<usize>>>,
+    /// What replaces each of those parts with --replace, which is empty for the parts after
+    /// the first line of a match
+    replacements: Option<Vec<Vec<u8>>>,
+}
+
+impl Line {
+    /// The text without its line ending, which patterns are matched against
+    fn content(&self) -> &[u8] {
+        match self.terminated {
+            true => &self.text[..self.text.len() - 1],
+            false => &self.text,
+        }
+    }
+
+    fn is_match(&self, matcher: &Matcher) -> MatchResult<bool> {
+        match &self.matches {
+            Some(matches) => Ok(!matches.is_empty()),
+            None => matcher.is_match(self.content()),
+        }
+    }
+
+    fn find_iter(&self, matcher: &Matcher) -> MatchResult<Vec<Range<usize>>> {
+        match &self.matches {
+            Some(matches) => Ok(matches.clone()),
+            None => matcher.find_iter(self.content()),
+        }
+    }
+
+    /// Returns what replaces each of the matches that `find_iter` returned
+    fn replacements(
+        &self,
+        matches: &[Range<usize>],
+        matcher: &Matcher,
+        template: &Template,
+    ) -> MatchResult<Vec<Vec<u8>>> {
+        match &self.replacements {
+            Some(replacements) => Ok(replacements.clone()),
+            None => matches
+                .iter()
+                .map(|found| matcher.replacement(self.content(), found.clone(), template))
+                .collect(),
+        }
+    }
+}
+
+/// A match in a line, with what's printed for it
+type PrintedMatch<'a> = (Range<usize>, Cow<'a, [u8]>);
+
+/// What is printed for each file
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Report {
+    Lines,
+    Count,
+    FilesWithMatches,
+    FilesWithoutMatch,
+    Quiet,
+    Json,
+}
+
+/// What the search of a file found besides its output
+struct Searched {
+    match_count: usize,
+    /// The matches in selected lines, only counted for JSON stats
+    matches: usize,
+    bytes_searched: usize,
+    binary_offset: Option<usize>,
+}
+
+/// The whole output of a file searched on a walker thread
+pub struct Buffered {
+    pub path: PathBuf,
+    output: Vec<u8>,
+    pub match_count: usize,
+    /// Whether any selected lines were printed, so a separator may go before them
+    printed: bool,
+    stats: Stats,
+}
+
+/// Prints the selected lines of each file with their context
+#[derive(Clone)]
+pub struct Printer {
+    invert_match: bool,
+    report: Report,
+    binary_files: BinaryFiles,
+    max_count: Option<usize>,
+    line_number: bool,
+    byte_offset: bool,
+    only_matching: bool,
+    before_context: usize,
+    after_context: usize,
+    /// Search each file as a whole, for patterns that match across lines
+    multiline: bool,
+    /// The byte that ends lines, which is NUL with -z
+    terminator: u8,
+    /// Follow file names with NUL instead of `:` or a newline
+    null: bool,
+    /// What replaces each match in the output, or in the files with --in-place
+    replace: Option<Template>,
+    in_place: bool,
+    /// Keep the original of each file rewritten with --in-place
+    backup: bool,
+    colors: Colors,
+    /// Whether any lines have been printed, so the next group of context needs a separator
+    printed: bool,
+    /// The totals of the files searched, for the JSON summary
+    stats: Stats,
+    started: Instant,
+}
+
+impl Printer {
+    pub fn new(cli: &Cli) -> Self {
+        let color = match cli.color {
+            ColorChoice::Auto => io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        };
+        let colors = match env::var("GREP_COLORS") {
+            _ if !color => Colors::default(),
+            Ok(spec) => Colors::parse(&spec),
+            Err(_) => Colors::standard(),
+        };
+        // Context lines have no matches to print
+        let context = |size: Option<usize>| match cli.only_matching {
+            true => 0,
+            false => size.or(cli.context).unwrap_or(0),
+        };
+        // Files edited in place are only reported by name or count if asked
+        let report = if cli.quiet || (cli.in_place && !(cli.files_with_matches || cli.count)) {
+            Report::Quiet
+        } else if cli.json {
+            Report::Json
+        } else if cli.files_with_matches {
+            Report::FilesWithMatches
+        } else if cli.files_without_match {
+            Report::FilesWithoutMatch
+        } else if cli.count {
+            Report::Count
+        } else {
+            Report::Lines
+        };
+        Self {
+            invert_match: cli.invert_match,
+            report,
+            binary_files: if cli.text {
+                BinaryFiles::Text
+            } else if cli.skip_binary {
+                BinaryFiles::WithoutMatch
+            } else {
+                cli.binary_files
+            },
+            max_count: cli.max_count,
+            line_number: cli.line_number,
+            byte_offset: cli.byte_offset,
+            only_matching: cli.only_matching,
+            before_context: context(cli.before_context),
+            after_context: context(cli.after_context),
+            multiline: cli.multiline,
+            terminator: if cli.null_data { b'\0' } else { b'\n' },
+            null: cli.null,
+            replace: cli.replace.as_deref().map(Template::parse),
+            in_place: cli.in_place,
+            backup: cli.backup,
+            colors,
+            printed: false,
+            stats: Stats::default(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Whether groups of lines that aren't next to each other are separated by `--`
+    fn separates_groups(&self) -> bool {
+        self.report == Report::Lines && (self.before_context > 0 || self.after_context > 0)
+    }
+
+    /// Searches a file into a buffer, so that files searched at the same time aren't mixed
+    pub fn search_buffered(&self, path: &Path, matcher: &Matcher) -> CliResult<Buffered> {
+        let mut printer = Self {
+            printed: false,
+            stats: Stats::default(),
+            ..self.clone()
+        };
+        let mut output = vec![];
+        let match_count = printer.print_file_matches(&mut output, path, matcher, true)?;
+        Ok(Buffered {
+            path: path.to_path_buf(),
+            output,
+            match_count,
+            printed: printer.printed,
+            stats: printer.stats,
+        })
+    }
+
+    /// Writes the output of a file searched with `search_buffered`, after a separator if it
+    /// starts a new group of context
+    pub fn print_buffered(&mut self, out: &mut impl Write, buffered: &Buffered) {
+        if self.separates_groups() && self.printed && buffered.printed {
+            let _ = writeln!(out, "{}", self.colors.separator.paint("--"));
+        }
+        let _ = out.write_all(&buffered.output);
+        self.printed |= buffered.printed;
+        self.stats += buffered.stats;
+    }
+
+    /// Writes the JSON summary after the last file
+    pub fn finish(&self, out: &mut impl Write) {
+        if self.report == Report::Json {
+            Message::Summary {
+                elapsed_total: self.started.elapsed(),
+                stats: self.stats,
+            }
+            .write(out);
+        }
+    }
+
+    /// Returns the number of selected lines, which may stop short at the first one when
+    /// only the file name or the exit status is reported
+    pub fn print_file_matches(
+        &mut self,
+        out: &mut impl Write,
+        path: &Path,
+        matcher: &Matcher,
+        print_filename: bool,
+    ) -> CliResult<usize> {
+        if self.report != Report::Json {
+            let searched = self.search_file(out, path, matcher, print_filename)?;
+            if let Some(template) = &self.replace
+                && self.in_place
+                && searched.match_count > 0
+                && searched.binary_offset.is_none()
+            {
+                let terminator = (!self.multiline).then_some(self.terminator);
+                let replace = |contents: &[u8]| {
+                    replace::replace_all(contents, matcher, template, terminator, self.max_count)
+                        .context(MatchSnafu { path })
+                };
+                replace::rewrite_file(path, replace, self.backup)?;
+            }
+            return Ok(searched.match_count);
+        }
+
+        // The lines of a file are only written between its begin and end messages, which
+        // are left out if nothing matched
+        let start = Instant::now();
+        let mut messages = vec![];
+        let searched = self.search_file(&mut messages, path, matcher, true)?;
+        let stats = Stats {
+            elapsed: start.elapsed(),
+            searches: 1,
+            searches_with_match: usize::from(searched.match_count > 0),
+            bytes_searched: searched.bytes_searched,
+            matched_lines: searched.match_count,
+            matches: searched.matches,
+        };
+        if searched.match_count > 0 {
+            Message::Begin {
+                path: Data::from_path(path),
+            }
+            .write(out);
+            let _ = out.write_all(&messages);
+            Message::End {
+                path: Data::from_path(path),
+                binary_offset: searched.binary_offset,
+                stats,
+            }
+            .write(out);
+        }
+        self.stats += stats;
+        Ok(searched.match_count)
+    }
+
+    /// Reads the lines of a file one at a time, or maps the whole file with -U
+    fn search_file(
+        &mut self,
+        out: &mut impl Write,
+        path: &Path,
+        matcher: &Matcher,
+        print_filename: bool,
+    ) -> CliResult<Searched> {
+        let terminator = self.terminator;
+        if self.multiline {
+            let contents = input::read_all(path).context(IoPathSnafu { path })?;
+            let binary_offset = self.binary_offset(&contents[..contents.len().min(BLOCK_SIZE)]);
+            let matches = matcher.find_iter(&contents).context(MatchSnafu { path })?;
+            let replacements = self
+                .replace
+                .as_ref()
+                .map(|template| {
+                    matches
+                        .iter()
+                        .map(|found| matcher.replacement(&contents, found.clone(), template))
+                        .collect()
+                })
+                .transpose()
+                .context(MatchSnafu { path })?;
+            let lines = spanned_lines(&contents, matches, replacements, terminator);
+            return self.search(
+                out,
+                path,
+                lines.map(Ok),
+                binary_offset,
+                matcher,
+                print_filename,
+            );
+        }
+
+        let mut buffer = utils::reader_from_path(path).context(IoPathSnafu { path })?;
+        let binary_offset = self.binary_offset(buffer.fill_buf().context(IoPathSnafu { path })?);
+        let mut offset = 0;
+        let lines = (1..).map_while(move |number| {
+            let mut text = vec![];
+            match buffer.read_until(terminator, &mut text) {
+                Ok(0) => None,
+                Ok(_) => {
+                    let line = Line {
+                        number,
+                        offset,
+                        terminated: text.last() == Some(&terminator),
+                        text,
+                        matches: None,
+                        replacements: None,
+                    };
+                    offset += line.text.len();
+                    Some(Ok(line))
+                }
+                Err(err) => Some(Err(err)),
+            }
+        });
+        self.search(out, path, lines, binary_offset, matcher, print_filename)
+    }
+
+    /// Like grep, only the first block is checked for the NUL bytes of binary data, which
+    /// can't be told apart from the ends of lines with -z
+    fn binary_offset(&self, block: &[u8]) -> Option<usize> {
+        match self.binary_files {
+            BinaryFiles::Text => None,
+            _ if self.terminator == b'\0' => None,
+            _ => block.iter().position(|&byte| byte == 0),
+        }
+    }
+
+    fn search(
+        &mut self,
+        out: &mut impl Write,
+        path: &Path,
+        lines: impl Iterator<Item = io::Result<Line>>,
+        binary_offset: Option<usize>,
+        matcher: &Matcher,
+        print_filename: bool,
+    ) -> CliResult<Searched> {
+        let binary = binary_offset.is_some();
+        if binary && self.binary_files == BinaryFiles::WithoutMatch {
+            return Ok(Searched {
+                match_count: 0,
+                matches: 0,
+                bytes_searched: 0,
+                binary_offset,
+            });
+        }
+        let filename = print_filename.then_some(path);
+        // The last lines that weren't printed, kept for the before-context of the next match
+        let mut before: VecDeque<Line> = VecDeque::with_capacity(self.before_context);
+        let mut after_left = 0;
+        let mut last_printed: Option<usize> = None;
+        let mut match_count: usize = 0;
+        let mut matches = 0;
+        let mut bytes_searched = 0;
+        for line in lines {
+            let line = line.context(IoPathSnafu { path })?;
+            bytes_searched = line.offset + line.text.len();
+            if self.max_count.is_some_and(|max| match_count >= max) {
+                // Only the trailing context of the last selected line is left
+                if after_left == 0 {
+                    break;
+                }
+                self.print_line(out, filename, &line, matcher, false)
+                    .context(MatchSnafu { path })?;
+                after_left -= 1;
+                continue;
+            }
+            let selected =
+                line.is_match(matcher).context(MatchSnafu { path })? != self.invert_match;
+            if selected {
+                match_count += 1;
+                if self.report == Report::Json {
+                    matches += line.find_iter(matcher).context(MatchSnafu { path })?.len();
+                }
+            }
+            match self.report {
+                // The lines of binary files are left out
+                Report::Lines | Report::Json if !binary => {}
+                Report::Count => continue,
+                _ if selected => break,
+                _ => continue,
+            }
+            if selected {
+                let first = line.number - before.len();
+                if self.separates_groups()
+                    && self.printed
+                    && last_printed.is_none_or(|last| last + 1 < first)
+                {
+                    let _ = writeln!(out, "{}", self.colors.separator.paint("--"));
+                }
+                for context in before.drain(..) {
+                    self.print_line(out, filename, &context, matcher, false)
+                        .context(MatchSnafu { path })?;
+                }
+                self.print_line(out, filename, &line, matcher, true)
+                    .context(MatchSnafu { path })?;
+                self.printed = true;
+                last_printed = Some(line.number);
+                after_left = self.after_context;
+            } else if after_left > 0 {
+                self.print_line(out, filename, &line, matcher, false)
+                    .context(MatchSnafu { path })?;
+                last_printed = Some(line.number);
+                after_left -= 1;
+            } else if self.before_context > 0 {
+                if before.len() == self.before_context {
+                    before.pop_front();
+                }
+                before.push_back(line);
+            }
+        }
+        let name = self.colors.file_name.paint(path.display().to_string());
+        let _ = match self.report {
+            Report::Lines if binary && match_count > 0 => {
+                writeln!(out, "Binary file {} matches", path.display())
+            }
+            Report::Count if print_filename => {
+                writeln!(out, "{name}{}{match_count}", self.name_separator(":"))
+            }
+            Report::Count => writeln!(out, "{match_count}"),
+            Report::FilesWithMatches if match_count > 0 => {
+                write!(out, "{name}{}", self.name_separator("\n"))
+            }
+            Report::FilesWithoutMatch if match_count == 0 => {
+                write!(out, "{name}{}", self.name_separator("\n"))
+            }
+            _ => Ok(()),
+        };
+
+        Ok(Searched {
+            match_count,
+            matches,
+            bytes_searched,
+            binary_offset,
+        })
+    }
+
+    /// Prints a selected or context line with its prefixes, or each of its matches with -o
+    fn print_line(
+        &self,
+        out: &mut impl Write,
+        filename: Option<&Path>,
+        line: &Line,
+        matcher: &Matcher,
+        selected: bool,
+    ) -> MatchResult<()> {
+        let text = line.content();
+        // The file name is always given with JSON
+        if self.report == Report::Json
+            && let Some(path) = filename
+        {
+            let matches = line.find_iter(matcher)?;
+            let replacements = self
+                .replace
+                .as_ref()
+                .map(|template| line.replacements(&matches, matcher, template))
+                .transpose()?;
+            let lines = json::Lines {
+                path: Data::from_path(path),
+                lines: Data::new(&line.text),
+                line_number: line.number,
+                absolute_offset: line.offset,
+                submatches: matches
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, found)| {
+                        let replacement = replacements.as_ref().map(|r| r[i].as_slice());
+                        Submatch::new(text, found, replacement)
+                    })
+                    .collect(),
+            };
+            match selected {
+                true => Message::Match(lines),
+                false => Message::Context(lines),
+            }
+            .write(out);
+            return Ok(());
+        }
+        // Only the lines selected by -v have no matches
+        let match_style = match (selected, self.invert_match) {
+            (true, false) => Some(self.colors.selected_match),
+            (false, true) => Some(self.colors.context_match),
+            _ => None,
+        };
+        if self.only_matching {
+            if match_style.is_none() {
+                return Ok(());
+            }
+            for (found, printed) in self.printed_matches(line, matcher)? {
+                let prefix = self.prefix(filename, line.number, line.offset + found.start, ':');
+                let style = self.colors.selected_match;
+                let _ = write!(out, "{prefix}{}", style.prefix())
+                    .and_then(|_| out.write_all(&printed))
+                    .and_then(|_| write!(out, "{}", style.suffix()))
+                    .and_then(|_| out.write_all(&[self.terminator]));
+            }
+            return Ok(());
+        }
+
+        let separator = if selected { ':' } else { '-' };
+        let prefix = self.prefix(filename, line.number, line.offset, separator);
+        let _ = write!(out, "{prefix}");
+        let mut last = 0;
+        if let Some(style) = match_style
+            && (style != Style::default() || self.replace.is_some())
+        {
+            for (found, printed) in self.printed_matches(line, matcher)? {
+                let _ = out
+                    .write_all(&text[last..found.start])
+                    .and_then(|_| write!(out, "{}", style.prefix()))
+                    .and_then(|_| out.write_all(&printed))
+                    .and_then(|_| write!(out, "{}", style.suffix()));
+                last = found.end;
+            }
+        }
+        let _ = out.write_all(&line.text[last..]);
+        Ok(())
+    }
+
+    /// Returns the matches in a line with what's printed for each: the match itself, or what
+    /// replaces it with --replace. Empty matches are left out unless they're replaced.
+    fn printed_matches<'a>(
+        &self,
+        line: &'a Line,
+        matcher: &Matcher,
+    ) -> MatchResult<Vec<PrintedMatch<'a>>> {
+        let matches = line.find_iter(matcher)?;
+        Ok(match &self.replace {
+            Some(template) => {
+                let replacements = line.replacements(&matches, matcher, template)?;
+                matches
+                    .into_iter()
+                    .zip(replacements.into_iter().map(Cow::Owned))
+                    .collect()
+            }
+            None => matches
+                .into_iter()
+                .filter(|found| !found.is_empty())
+                .map(|found| (found.clone(), Cow::Borrowed(&line.content()[found])))
+                .collect(),
+        })
+    }
+
+    /// Returns what follows a file name, which is NUL with --null
+    fn name_separator(&self, separator: &str) -> String {
+        match (self.null, separator) {
+            (true, _) => "\0".to_string(),
+            (false, "\n") => separator.to_string(),
+            (false, _) => self.colors.separator.paint(separator).to_string(),
+        }
+    }
+
+    /// Returns the file name, line number and byte offset that are shown, each followed by
+    /// `:` on selected lines and `-` on context lines
+    fn prefix(
+        &self,
+        filename: Option<&Path>,
+        number: usize,
+        offset: usize,
+        separator: char,
+    ) -> String {
+        let separator = separator.to_string();
+        let mut prefix = String::new();
+        if let Some(path) = filename {
+            let name = self.colors.file_name.paint(path.display().to_string());
+            prefix += &format!("{name}{}", self.name_separator(&separator));
+        }
+        let separator = self.colors.separator.paint(separator);
+        if self.line_number {
+            let number = self.colors.line_number.paint(number.to_string());
+            prefix += &format!("{number}{separator}");
+        }
+        if self.byte_offset {
+            let offset = self.colors.byte_offset.paint(offset.to_string());
+            prefix += &format!("{offset}{separator}");
+        }
+        prefix
+    }
+}
+
+/// Splits the contents of a file searched with -U into lines, with the parts of `matches`
+/// each one holds. A match selects every line it spans.
+fn spanned_lines(
+    contents: &[u8],
+    matches: Vec<Range<usize>>,
+    replacements: Option<Vec<Vec<u8>>>,
+    terminator: u8,
+) -> impl Iterator<Item = Line> {
+    let mut first = 0;
+    let mut start = 0;
+    (1..).map_while(move |number| {
+        if start == contents.len() {
+            return None;
+        }
+        let end = contents[start..]
+            .iter()
+            .position(|&byte| byte == terminator)
+            .map_or(contents.len(), |i| start + i + 1);
+        let text = &contents[start..end];
+        let terminated = text.last() == Some(&terminator);
+        let content_end = if terminated { end - 1 } else { end };
+        // Matches are in order and don't overlap, so those that end before the line are done
+        while first < matches.len() && matches[first].start < start && matches[first].end <= start {
+            first += 1;
+        }
+        let spanned = matches[first..]
+            .iter()
+            .take_while(|found| found.start < end)
+            .count();
+        let line = Line {
+            number,
+            offset: start,
+            text: text.to_vec(),
+            terminated,
+            matches: Some(
+                matches[first..first + spanned]
+                    .iter()
+                    .map(|found| {
+                        let found_start = found.start.clamp(start, content_end);
+                        found_start - start..found.end.clamp(found_start, content_end) - start
+                    })
+                    .collect(),
+            ),
+            // A match is replaced on the line it starts on
+            replacements: replacements.as_ref().map(|replacements| {
+                (first..first + spanned)
+                    .map(|i| match matches[i].start >= start {
+                        true => replacements[i].clone(),
+                        false => vec![],
+                    })
+                    .collect()
+            }),
+        };
+        start = end;
+        Some(line)
+    })
+}
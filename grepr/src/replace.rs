@@ -0,0 +1,206 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use snafu::ResultExt;
+use tempfile::NamedTempFile;
+
+use crate::{
+    CliResult, IoPathSnafu,
+    matcher::{MatchResult, Matcher},
+};
+
+/// A part of a `--replace` template
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Literal(Vec<u8>),
+    /// `$1` or `${1}`
+    Index(usize),
+    /// `$name` or `${name}`
+    Name(String),
+}
+
+/// What each match is replaced with, following the syntax of `regex`'s `Captures::expand`:
+/// `$1`, `$name`, `${name}`, and `$$` for a dollar sign. Groups that didn't match are empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template(Vec<Piece>);
+
+impl Template {
+    pub fn parse(template: &str) -> Self {
+        let mut pieces = vec![];
+        let mut literal = vec![];
+        let mut rest = template;
+        while let Some(dollar) = rest.find('$') {
+            literal.extend_from_slice(&rest.as_bytes()[..dollar]);
+            rest = &rest[dollar + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                literal.push(b'$');
+                rest = after;
+                continue;
+            }
+            let (name, after) = match rest.strip_prefix('{') {
+                Some(braced) => match braced.split_once('}') {
+                    Some((name, after)) if !name.is_empty() => (name, after),
+                    _ => ("", rest),
+                },
+                None => {
+                    let end = rest
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                        .unwrap_or(rest.len());
+                    rest.split_at(end)
+                }
+            };
+            // A dollar sign that isn't followed by a group is kept as it is
+            if name.is_empty() {
+                literal.push(b'$');
+                continue;
+            }
+            if !literal.is_empty() {
+                pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+            }
+            pieces.push(match name.parse() {
+                Ok(index) => Piece::Index(index),
+                Err(_) => Piece::Name(name.to_string()),
+            });
+            rest = after;
+        }
+        literal.extend_from_slice(rest.as_bytes());
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Self(pieces)
+    }
+
+    /// Expands the template with the groups that `index` and `name` look up
+    pub fn expand<'a>(
+        &self,
+        index: impl Fn(usize) -> Option<&'a [u8]>,
+        name: impl Fn(&str) -> Option<&'a [u8]>,
+    ) -> Vec<u8> {
+        let mut expanded = vec![];
+        for piece in &self.0 {
+            let bytes = match piece {
+                Piece::Literal(bytes) => Some(bytes.as_slice()),
+                Piece::Index(i) => index(*i),
+                Piece::Name(group) => name(group),
+            };
+            expanded.extend_from_slice(bytes.unwrap_or_default());
+        }
+        expanded
+    }
+}
+
+/// Returns `text` with the matches in it replaced, in every line or only the first `max`
+/// lines with matches. `None` is returned if nothing matched.
+pub fn replace_all(
+    text: &[u8],
+    matcher: &Matcher,
+    template: &Template,
+    terminator: Option<u8>,
+    max: Option<usize>,
+) -> MatchResult<Option<Vec<u8>>> {
+    let lines: Box<dyn Iterator<Item = &[u8]>> = match terminator {
+        Some(terminator) => Box::new(text.split_inclusive(move |&byte| byte == terminator)),
+        None => Box::new([text].into_iter()),
+    };
+    let mut replaced = Vec::with_capacity(text.len());
+    let mut changed = 0;
+    for line in lines {
+        let content = match terminator {
+            Some(terminator) => line.strip_suffix(&[terminator]).unwrap_or(line),
+            None => line,
+        };
+        let matches = matcher.find_iter(content)?;
+        if matches.is_empty() || max.is_some_and(|max| changed >= max) {
+            replaced.extend_from_slice(line);
+            continue;
+        }
+        let mut last = 0;
+        for found in matches {
+            replaced.extend_from_slice(&content[last..found.start]);
+            replaced.extend(matcher.replacement(content, found.clone(), template)?);
+            last = found.end;
+        }
+        replaced.extend_from_slice(&line[last..]);
+        changed += 1;
+    }
+    Ok((changed > 0).then_some(replaced))
+}
+
+/// Rewrites a file with its matches replaced, through a temporary file in the same directory
+/// that takes its place, so that it's never left half written. The original is kept with a
+/// `.bak` extension added if `backup` is set.
+pub fn rewrite_file(
+    path: &Path,
+    replace: impl FnOnce(&[u8]) -> CliResult<Option<Vec<u8>>>,
+    backup: bool,
+) -> CliResult<()> {
+    // Links are written through rather than replaced by a file
+    let path = fs::canonicalize(path).context(IoPathSnafu { path })?;
+    let contents = fs::read(&path).context(IoPathSnafu { path: &path })?;
+    let Some(replaced) = replace(&contents)? else {
+        return Ok(());
+    };
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut file = NamedTempFile::new_in(dir).context(IoPathSnafu { path: dir })?;
+    let permissions = fs::metadata(&path)
+        .context(IoPathSnafu { path: &path })?
+        .permissions();
+    file.write_all(&replaced)
+        .and_then(|_| file.as_file().set_permissions(permissions))
+        .and_then(|_| file.as_file().sync_all())
+        .context(IoPathSnafu { path: file.path() })?;
+    if backup {
+        let mut backup = path.clone().into_os_string();
+        backup.push(".bak");
+        let backup = PathBuf::from(backup);
+        fs::copy(&path, &backup).context(IoPathSnafu { path: backup })?;
+    }
+    file.persist(&path)
+        .map_err(|err| err.error)
+        .context(IoPathSnafu { path })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        use Piece::*;
+        assert_eq!(
+            Template::parse("a${1}b${name}c$x_1.$$"),
+            Template(vec![
+                Literal(b"a".to_vec()),
+                Index(1),
+                Literal(b"b".to_vec()),
+                Name("name".to_string()),
+                Literal(b"c".to_vec()),
+                Name("x_1".to_string()),
+                Literal(b".$".to_vec()),
+            ])
+        );
+        assert_eq!(
+            Template::parse("$1b${2}0 $ ${} ${x"),
+            Template(vec![
+                Name("1b".to_string()),
+                Index(2),
+                Literal(b"0 $ ${} ${x".to_vec())
+            ])
+        );
+    }
+
+    #[test]
+    fn test_expand() {
+        let template = Template::parse("<$2|$1|${word}|$9>");
+        let groups: [&[u8]; 3] = [b"ab", b"a", b"b"];
+        let expanded = template.expand(
+            |i| groups.get(i).copied(),
+            |name| (name == "word").then_some(b"w".as_slice()),
+        );
+        assert_eq!(expanded, b"<b|a|w|>");
+    }
+}
@@ -0,0 +1,65 @@
+use std::path::Path;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::{DirEntry, WalkBuilder, WalkParallel};
+use snafu::ResultExt;
+
+use crate::{Cli, CliResult, GlobSnafu};
+
+/// Decides which entries below a directory searched with -r are walked, by their names
+#[derive(Clone)]
+pub struct Filter {
+    /// Files must match one of these if any are given
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    exclude_dir: GlobSet,
+    /// Whether `.git` directories are walked
+    git_dir: bool,
+}
+
+impl Filter {
+    pub fn new(cli: &Cli) -> CliResult<Self> {
+        Ok(Self {
+            include: match cli.include.is_empty() {
+                true => None,
+                false => Some(glob_set(&cli.include)?),
+            },
+            exclude: glob_set(&cli.exclude)?,
+            exclude_dir: glob_set(&cli.exclude_dir)?,
+            git_dir: cli.no_ignore,
+        })
+    }
+
+    fn allows(&self, entry: &DirEntry) -> bool {
+        let name = entry.file_name();
+        if entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_dir())
+        {
+            (self.git_dir || name != ".git") && !self.exclude_dir.is_match(name)
+        } else {
+            self.include.as_ref().is_none_or(|set| set.is_match(name))
+                && !self.exclude.is_match(name)
+        }
+    }
+}
+
+fn glob_set(globs: &[String]) -> CliResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).context(GlobSnafu)?);
+    }
+    builder.build().context(GlobSnafu)
+}
+
+/// Returns a walker over `root` on `threads` threads, which skips hidden entries and the
+/// ones that ignore files or `filter` leave out
+pub fn walker(root: &Path, cli: &Cli, filter: &Filter, threads: usize) -> WalkParallel {
+    let filter = filter.clone();
+    WalkBuilder::new(root)
+        .threads(threads)
+        .standard_filters(!cli.no_ignore)
+        .hidden(!cli.hidden)
+        .filter_entry(move |entry| filter.allows(entry))
+        .build_parallel()
+}
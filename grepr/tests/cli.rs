@@ -11,6 +11,8 @@ const BUSTLE: &str = "tests/inputs/bustle.txt";
 const EMPTY: &str = "tests/inputs/empty.txt";
 const FOX: &str = "tests/inputs/fox.txt";
 const NOBODY: &str = "tests/inputs/nobody.txt";
+const BINARY: &str = "tests/inputs/binary.bin";
+const LATIN1: &str = "tests/inputs/latin1.txt";
 const INPUTS_DIR: &str = "tests/inputs";
 
 // --------------------------------------------------
@@ -65,6 +67,12 @@ fn warns_bad_file() -> Result<()> {
 
 // --------------------------------------------------
 fn run(args: &[&str], expected_file: &str) -> Result<()> {
+    run_code(args, expected_file, None)
+}
+
+// --------------------------------------------------
+/// Like `run`, with the exit status given for when it isn't 1 for no output and 0 otherwise
+fn run_code(args: &[&str], expected_file: &str, code: Option<i32>) -> Result<()> {
     let windows_file = format!("{expected_file}.windows");
     let expected_file = if cfg!(windows)
         && Path::new(&windows_file).is_file()
@@ -76,7 +84,9 @@ fn run(args: &[&str], expected_file: &str) -> Result<()> {
 
     let expected = fs::read_to_string(expected_file)?;
     let output = Command::cargo_bin(PRG)?.args(args).output().expect("fail");
-    assert!(output.status.success());
+    // Exit status 1 means no lines were selected, which is usually when nothing is printed
+    let code = code.unwrap_or(if expected.is_empty() { 1 } else { 0 });
+    assert_eq!(output.status.code(), Some(code));
 
     let stdout = String::from_utf8(output.stdout).expect("invalid UTF-8");
     assert_eq!(stdout, expected);
@@ -200,7 +210,12 @@ fn insensitive_count() -> Result<()> {
 // --------------------------------------------------
 #[test]
 fn nobody_count() -> Result<()> {
-    run(&["-c", "nobody", NOBODY], "tests/expected/nobody.txt.count")
+    // A count of 0 is printed, but no lines were selected
+    run_code(
+        &["-c", "nobody", NOBODY],
+        "tests/expected/nobody.txt.count",
+        Some(1),
+    )
 }
 
 // --------------------------------------------------
@@ -287,3 +302,896 @@ fn stdin_insensitive_count() -> Result<()> {
     assert_eq!(stdout, expected);
     Ok(())
 }
+
+// --------------------------------------------------
+#[test]
+fn context_multiple_files() -> Result<()> {
+    run(
+        &["-C1", "the", BUSTLE, NOBODY],
+        "tests/expected/all.the.context1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn before_context_insensitive() -> Result<()> {
+    run(
+        &["-B", "1", "-i", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.before1.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn after_context() -> Result<()> {
+    run(
+        &["--after-context", "1", "To", NOBODY],
+        "tests/expected/nobody.txt.to.after1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn after_context_invert() -> Result<()> {
+    run(
+        &["-v", "-A1", "-i", "e", BUSTLE],
+        "tests/expected/bustle.txt.e.invert.after1",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn context_count() -> Result<()> {
+    run(
+        &["-c", "-C2", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn context_line_number() -> Result<()> {
+    run(
+        &["-n", "-C1", "the", BUSTLE, NOBODY],
+        "tests/expected/all.the.context1.line_number",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn byte_offset() -> Result<()> {
+    run(
+        &["--byte-offset", "-i", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.byte_offset.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn only_matching() -> Result<()> {
+    run(
+        &["-onb", "-i", "the", BUSTLE, NOBODY],
+        "tests/expected/all.the.only_matching.insensitive",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn only_matching_invert() -> Result<()> {
+    // Lines are selected, but they have no matches to print
+    run_code(&["-o", "-v", "the", BUSTLE], "tests/expected/empty.foo", Some(0))
+}
+
+// --------------------------------------------------
+#[test]
+fn color() -> Result<()> {
+    run(
+        &["--color=always", "-n", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.color",
+    )?;
+    // Not a terminal
+    run(
+        &["--color", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn grep_colors() -> Result<()> {
+    let expected = fs::read_to_string("tests/expected/bustle.txt.the.grep_colors")?;
+    let output = Command::cargo_bin(PRG)?
+        .args(["--color=always", "-n", "the", BUSTLE])
+        .env("GREP_COLORS", "ms=04;32:ln=1:se=")
+        .output()
+        .expect("fail");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).expect("invalid UTF-8");
+    assert_eq!(stdout, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_patterns() -> Result<()> {
+    run(
+        &["-e", "the", "--regexp", "Nobody", NOBODY, BUSTLE],
+        "tests/expected/all.e.the.e.nobody",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn patterns_file() -> Result<()> {
+    run(
+        &["-n", "-f", "tests/inputs/patterns.txt", NOBODY],
+        "tests/expected/nobody.txt.patterns_file",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn fixed_strings_word() -> Result<()> {
+    run(
+        &["-F", "-w", "-o", "-i", "the", BUSTLE, NOBODY, FOX],
+        "tests/expected/all.the.word.only_matching.fixed.insensitive",
+    )?;
+    run(&["-F", "fox.", FOX], "tests/expected/empty.foo")
+}
+
+// --------------------------------------------------
+#[test]
+fn word_regexp() -> Result<()> {
+    run(
+        &["--word-regexp", "the", BUSTLE, NOBODY, FOX],
+        "tests/expected/all.the.word",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn line_regexp() -> Result<()> {
+    run(
+        &["-x", "-i", "the bustle in a house", BUSTLE],
+        "tests/expected/bustle.txt.line.insensitive",
+    )?;
+    run(&["-x", "The bustle", BUSTLE], "tests/expected/empty.foo")
+}
+
+// --------------------------------------------------
+#[test]
+fn invert_match() -> Result<()> {
+    run(
+        &["-v", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.invert",
+    )?;
+    run(
+        &["--invert-match", "-c", "-i", "the", BUSTLE, NOBODY],
+        "tests/expected/all.the.invert.insensitive.count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn files_with_matches() -> Result<()> {
+    run(
+        &["-l", "the", BUSTLE, NOBODY, FOX, EMPTY],
+        "tests/expected/all.the.files_with_matches",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn files_without_match() -> Result<()> {
+    run(
+        &["--files-without-match", "the", BUSTLE, NOBODY, FOX, EMPTY],
+        "tests/expected/all.the.files_without_match",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn max_count() -> Result<()> {
+    run(
+        &["-m1", "the", BUSTLE, NOBODY],
+        "tests/expected/all.the.max_count1",
+    )?;
+    run(
+        &["--max-count", "1", "-A2", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.max_count1.after2",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-q", "the", BUSTLE])
+        .assert()
+        .code(0)
+        .stdout("");
+    Command::cargo_bin(PRG)?
+        .args(["--quiet", "nothing", BUSTLE])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exit_status() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["nothing", BUSTLE, NOBODY])
+        .assert()
+        .code(1);
+    Command::cargo_bin(PRG)?
+        .args(["the", &gen_bad_file(), BUSTLE])
+        .assert()
+        .code(2);
+    // A match makes up for the error with -q
+    Command::cargo_bin(PRG)?
+        .args(["-q", "the", &gen_bad_file(), BUSTLE])
+        .assert()
+        .code(0);
+    Command::cargo_bin(PRG)?
+        .args(["the", INPUTS_DIR])
+        .assert()
+        .code(2);
+    Command::cargo_bin(PRG)?
+        .args(["*foo", FOX])
+        .assert()
+        .code(2);
+    Ok(())
+}
+
+// --------------------------------------------------
+fn run_bytes(args: &[&str], expected_file: &str) -> Result<()> {
+    let expected = fs::read(expected_file)?;
+    let output = Command::cargo_bin(PRG)?.args(args).output().expect("fail");
+    assert!(matches!(output.status.code(), Some(0 | 1)));
+    assert_eq!(output.stdout, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_file_matches() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["the", BINARY, FOX])
+        .assert()
+        .success()
+        .stdout(
+            "Binary file tests/inputs/binary.bin matches\n\
+            tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\n",
+        );
+    Command::cargo_bin(PRG)?
+        .args(["-c", "the", BINARY])
+        .assert()
+        .success()
+        .stdout("1\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_files_text() -> Result<()> {
+    run_bytes(
+        &["-a", "-n", "the", BINARY],
+        "tests/expected/binary.bin.the.text",
+    )?;
+    run_bytes(
+        &["--binary-files=text", "-n", "the", BINARY],
+        "tests/expected/binary.bin.the.text",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_files_without_match() -> Result<()> {
+    for flag in ["-I", "--binary-files=without-match"] {
+        Command::cargo_bin(PRG)?
+            .args([flag, "the", BINARY])
+            .assert()
+            .code(1)
+            .stdout("");
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn invalid_utf8() -> Result<()> {
+    run_bytes(
+        &["-n", "-w", "latin", LATIN1],
+        "tests/expected/latin1.txt.latin.word",
+    )
+}
+
+// --------------------------------------------------
+/// Builds a repository with an ignore file, hidden entries and nested directories
+fn tree_fixture() -> Result<tempfile::TempDir> {
+    let dir = tempfile::tempdir()?;
+    let files = [
+        (".git/HEAD", "ref: refs/heads/main\n"),
+        (".git/description", "needle in .git\n"),
+        (".gitignore", "*.log\nbuild/\n"),
+        (".hidden.txt", "needle hidden\n"),
+        ("a.txt", "needle a\nhay\n"),
+        ("b.md", "needle b\n"),
+        ("debug.log", "needle log\n"),
+        ("build/out.txt", "needle build\n"),
+        ("src/c.txt", "needle c\n"),
+        ("src/deep/d.txt", "hay\nneedle d\n"),
+        ("vendor/e.txt", "needle e\n"),
+    ];
+    for (name, contents) in files {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, contents)?;
+    }
+    Ok(dir)
+}
+
+// --------------------------------------------------
+fn run_in(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::cargo_bin(PRG)?
+        .current_dir(dir)
+        .args(args)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    Ok(String::from_utf8(output)?)
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_skips_ignored_and_hidden() -> Result<()> {
+    let dir = tree_fixture()?;
+    assert_eq!(
+        run_in(dir.path(), &["-r", "--sort=path", "needle", "."])?,
+        "./a.txt:needle a\n\
+         ./b.md:needle b\n\
+         ./src/c.txt:needle c\n\
+         ./src/deep/d.txt:needle d\n\
+         ./vendor/e.txt:needle e\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_hidden_no_ignore() -> Result<()> {
+    let dir = tree_fixture()?;
+    assert_eq!(
+        run_in(dir.path(), &["-rl", "--sort=path", "--hidden", "needle", "."])?,
+        "./.hidden.txt\n./a.txt\n./b.md\n./src/c.txt\n./src/deep/d.txt\n./vendor/e.txt\n"
+    );
+    assert_eq!(
+        run_in(dir.path(), &["-rl", "--sort=path", "--no-ignore", "needle", "."])?,
+        "./a.txt\n./b.md\n./build/out.txt\n./debug.log\n./src/c.txt\n./src/deep/d.txt\n\
+         ./vendor/e.txt\n"
+    );
+    assert_eq!(
+        run_in(
+            dir.path(),
+            &["-rl", "--sort=path", "--hidden", "--no-ignore", "needle", "."]
+        )?,
+        "./.git/description\n./.hidden.txt\n./a.txt\n./b.md\n./build/out.txt\n./debug.log\n\
+         ./src/c.txt\n./src/deep/d.txt\n./vendor/e.txt\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_globs() -> Result<()> {
+    let dir = tree_fixture()?;
+    assert_eq!(
+        run_in(
+            dir.path(),
+            &[
+                "-rl",
+                "--sort=path",
+                "--include=*.txt",
+                "--exclude=c.*",
+                "--exclude-dir=vendor",
+                "needle",
+                "."
+            ]
+        )?,
+        "./a.txt\n./src/deep/d.txt\n"
+    );
+    assert_eq!(
+        run_in(
+            dir.path(),
+            &["-rl", "--sort=path", "--exclude-dir=s*", "--exclude=*.md", "needle", "."]
+        )?,
+        "./a.txt\n./vendor/e.txt\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_glob() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-r", "--include", "[", "needle", INPUTS_DIR])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error parsing glob '['"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_threads() -> Result<()> {
+    let dir = tree_fixture()?;
+    let expected = run_in(dir.path(), &["-rn", "-j1", "--sort=path", "-C1", "needle", "."])?;
+    assert_eq!(
+        expected,
+        "./a.txt:1:needle a\n\
+         ./a.txt-2-hay\n\
+         --\n\
+         ./b.md:1:needle b\n\
+         --\n\
+         ./src/c.txt:1:needle c\n\
+         --\n\
+         ./src/deep/d.txt-1-hay\n\
+         ./src/deep/d.txt:2:needle d\n\
+         --\n\
+         ./vendor/e.txt:1:needle e\n"
+    );
+    for threads in ["0", "4"] {
+        let args = ["-rn", "-j", threads, "--sort=path", "-C1", "needle", "."];
+        assert_eq!(run_in(dir.path(), &args)?, expected);
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_groups_files() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let contents = "needle\n".repeat(200);
+    for i in 0..20 {
+        fs::write(dir.path().join(format!("{i}.txt")), &contents)?;
+    }
+    let output = run_in(dir.path(), &["-r", "-j8", "needle", "."])?;
+    let mut files: Vec<&str> = output
+        .lines()
+        .map(|line| line.split_once(':').unwrap().0)
+        .collect();
+    assert_eq!(files.len(), 20 * 200);
+    // Each file's lines come in one piece
+    files.dedup();
+    assert_eq!(files.len(), 20);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_quiet() -> Result<()> {
+    let dir = tree_fixture()?;
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args(["-rq", "needle", "."])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+fn run_json(args: &[&str]) -> Result<Vec<serde_json::Value>> {
+    let output = Command::cargo_bin(PRG)?
+        .arg("--json")
+        .args(args)
+        .output()?;
+    assert!(matches!(output.status.code(), Some(0 | 1)));
+    let messages = String::from_utf8(output.stdout)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(messages)
+}
+
+// --------------------------------------------------
+#[test]
+fn json() -> Result<()> {
+    let messages = run_json(&["-A1", "dog", FOX, EMPTY, NOBODY])?;
+    let types: Vec<&str> = messages
+        .iter()
+        .map(|message| message["type"].as_str().unwrap())
+        .collect();
+    // Files without selected lines have no messages
+    assert_eq!(types, ["begin", "match", "end", "summary"]);
+    assert_eq!(messages[0]["data"]["path"]["text"], FOX);
+    assert_eq!(
+        messages[1]["data"],
+        serde_json::json!({
+            "path": {"text": FOX},
+            "lines": {"text": "The quick brown fox jumps over the lazy dog.\n"},
+            "line_number": 1,
+            "absolute_offset": 0,
+            "submatches": [{"match": {"text": "dog"}, "start": 40, "end": 43}],
+        })
+    );
+    let end = &messages[2]["data"];
+    assert!(end["binary_offset"].is_null());
+    assert_eq!(end["stats"]["matched_lines"], 1);
+    assert_eq!(end["stats"]["bytes_searched"], 45);
+    assert!(end["stats"]["elapsed"]["nanos"].is_u64());
+
+    let stats = &messages[3]["data"]["stats"];
+    assert_eq!(stats["searches"], 3);
+    assert_eq!(stats["searches_with_match"], 1);
+    assert_eq!(stats["matches"], 1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json_context() -> Result<()> {
+    let messages = run_json(&["-C1", "-i", "the", BUSTLE])?;
+    let lines: Vec<(&str, u64)> = messages[1..messages.len() - 2]
+        .iter()
+        .map(|message| {
+            let number = message["data"]["line_number"].as_u64().unwrap();
+            (message["type"].as_str().unwrap(), number)
+        })
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            ("match", 1),
+            ("match", 2),
+            ("context", 3),
+            ("context", 5),
+            ("match", 6),
+            ("context", 7)
+        ]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json_bytes() -> Result<()> {
+    let messages = run_json(&["latin", LATIN1])?;
+    let found = &messages[1]["data"];
+    // "Caf\xe9 the latin-1 line\n"
+    assert_eq!(found["lines"]["bytes"], "Q2Fm6SB0aGUgbGF0aW4tMSBsaW5lCg==");
+    assert_eq!(
+        found["submatches"],
+        serde_json::json!([{"match": {"text": "latin"}, "start": 9, "end": 14}])
+    );
+
+    let messages = run_json(&["lazy", BINARY])?;
+    assert_eq!(messages.len(), 3);
+    assert!(messages[1]["data"]["binary_offset"].is_u64());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_json_count() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--json", "-c", "the", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline() -> Result<()> {
+    let source = "fn foo(\n    a: i32,\n) {}\nfn bar() {}\n";
+    Command::cargo_bin(PRG)?
+        .args(["-Un", r"fn \w+\(\n\s+"])
+        .write_stdin(source)
+        .assert()
+        .success()
+        .stdout("1:fn foo(\n2:    a: i32,\n");
+    // Without -U, lines are matched one at a time
+    Command::cargo_bin(PRG)?
+        .args(["-n", r"fn \w+\(\n\s+"])
+        .write_stdin(source)
+        .assert()
+        .code(1)
+        .stdout("");
+    // ^ and $ match at the ends of each line
+    Command::cargo_bin(PRG)?
+        .args(["-Un", r"^\) \{\}$\n^fn"])
+        .write_stdin(source)
+        .assert()
+        .success()
+        .stdout("3:) {}\n4:fn bar() {}\n");
+    Command::cargo_bin(PRG)?
+        .args(["-UxF", "fn bar() {}"])
+        .write_stdin(source)
+        .assert()
+        .success()
+        .stdout("fn bar() {}\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline_file() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("poem.txt");
+    fs::write(&path, "roses are red\nviolets\nare blue\nsugar is sweet\n")?;
+    let path = path.to_str().unwrap();
+    Command::cargo_bin(PRG)?
+        .args(["-U", "-A1", "-b", "-o", r"violets\s+are", path])
+        .assert()
+        .success()
+        .stdout("14:violets\n22:are\n");
+    Command::cargo_bin(PRG)?
+        .args(["-Uv", r"violets\s+are", path])
+        .assert()
+        .success()
+        .stdout("roses are red\nsugar is sweet\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_data() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-z", "foo"])
+        .write_stdin("one\0two\nfoo\0three\0")
+        .assert()
+        .success()
+        .stdout("two\nfoo\0");
+    Command::cargo_bin(PRG)?
+        .args(["-zxo", "two.foo"])
+        .write_stdin("one\0two\nfoo\0three")
+        .assert()
+        .success()
+        .stdout("two\nfoo\0");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-l", "--null", "the", FOX, EMPTY, BUSTLE])
+        .assert()
+        .success()
+        .stdout(format!("{FOX}\0{BUSTLE}\0"));
+    Command::cargo_bin(PRG)?
+        .args(["-Z", "-c", "the", FOX, EMPTY])
+        .assert()
+        .success()
+        .stdout(format!("{FOX}\x001\n{EMPTY}\x000\n"));
+    Command::cargo_bin(PRG)?
+        .args(["-Zn", "dog", FOX, EMPTY])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{FOX}\x001:The quick brown fox jumps over the lazy dog.\n"
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn replace() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--replace", "${adj} cat", r"(?P<adj>\w+) fox", FOX])
+        .assert()
+        .success()
+        .stdout("The quick brown cat jumps over the lazy dog.\n");
+    Command::cargo_bin(PRG)?
+        .args(["-ow", "--replace", "<$1$$>", r"(\w)\w+", FOX])
+        .assert()
+        .success()
+        .stdout("<T$>\n<q$>\n<b$>\n<f$>\n<j$>\n<o$>\n<t$>\n<l$>\n<d$>\n");
+    Command::cargo_bin(PRG)?
+        .args(["--replace", "*", "-F", "o", FOX])
+        .assert()
+        .success()
+        .stdout("The quick br*wn f*x jumps *ver the lazy d*g.\n");
+    // Lines matched with -U are replaced from the start of each match
+    Command::cargo_bin(PRG)?
+        .args(["-U", "--replace", "$1, $2", r"(\w+),\n(\w+)"])
+        .write_stdin("roses,\nviolets\nsugar\n")
+        .assert()
+        .success()
+        .stdout("roses, violets\n\n");
+
+    let messages = run_json(&["--replace", "cat", "dog", FOX])?;
+    assert_eq!(
+        messages[1]["data"]["submatches"],
+        serde_json::json!([{
+            "match": {"text": "dog"},
+            "replacement": {"text": "cat"},
+            "start": 40,
+            "end": 43
+        }])
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn replace_in_place() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let files = [
+        ("a.txt", "the colour red\nno match\ncolours\n"),
+        ("b.txt", "nothing here\n"),
+        ("sub/c.txt", "colour"),
+        ("d.bin", "colour\0"),
+    ];
+    for (name, contents) in files {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, contents)?;
+    }
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args(["-r", "--in-place", "--backup", "--replace", "color", "colour", "."])
+        .assert()
+        .success()
+        .stdout("");
+    let read = |name: &str| fs::read_to_string(dir.path().join(name));
+    assert_eq!(read("a.txt")?, "the color red\nno match\ncolors\n");
+    assert_eq!(read("a.txt.bak")?, files[0].1);
+    assert_eq!(read("sub/c.txt")?, "color");
+    assert_eq!(read("sub/c.txt.bak")?, "colour");
+    // Files without matches and binary files are left alone
+    assert_eq!(read("b.txt")?, files[1].1);
+    assert!(!dir.path().join("b.txt.bak").exists());
+    assert_eq!(read("d.bin")?, "colour\0");
+
+    // -m limits the lines that are replaced, and -l names the files
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args(["-l", "-m1", "--in-place", "--replace=${1}o$1", "(l)", "a.txt"])
+        .assert()
+        .success()
+        .stdout("a.txt\n");
+    assert_eq!(read("a.txt")?, "the cololor red\nno match\ncolors\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_in_place() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--in-place", "foo", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--replace <TEMPLATE>"));
+    Command::cargo_bin(PRG)?
+        .args(["--in-place", "--replace", "bar", "foo"])
+        .write_stdin("foo\n")
+        .assert()
+        .failure()
+        .stderr("Standard input can't be edited in place\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn basic_regexp() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-G", r"\(foo\)\{2\}"])
+        .write_stdin("foofoo\nfoo\n(foo){2}\n")
+        .assert()
+        .success()
+        .stdout("foofoo\n");
+    // Operators without a backslash are literal, and so is a leading *
+    Command::cargo_bin(PRG)?
+        .args(["-G", "-o", "*(foo){2}|"])
+        .write_stdin("foofoo\n*(foo){2}|\n")
+        .assert()
+        .success()
+        .stdout("*(foo){2}|\n");
+    // Backreferences count the groups of earlier patterns
+    Command::cargo_bin(PRG)?
+        .args(["-G", "-e", r"\(a\)b\1", "-e", r"\(c\)\(d\)\2"])
+        .write_stdin("aba\nabb\ncdd\ncdc\n")
+        .assert()
+        .success()
+        .stdout("aba\ncdd\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn extended_regexp() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-E", "-o", r"([[:alpha:]])\1|\(x\)"])
+        .write_stdin("the little (x)\n")
+        .assert()
+        .success()
+        .stdout("tt\n(x)\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn pcre() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-P", "-o", r"\w+(?= dog)|(?<=quick )\w+", FOX])
+        .assert()
+        .success()
+        .stdout("brown\nlazy\n");
+    Command::cargo_bin(PRG)?
+        .args(["-P", "-w", "--replace", "<$1>", r"(\w)\1"])
+        .write_stdin("aa bbc dd\n")
+        .assert()
+        .success()
+        .stdout("<a> bbc <d>\n");
+    // Backreferences count the groups of earlier patterns
+    Command::cargo_bin(PRG)?
+        .args(["-P", "-e", r"(a)\1", "-e", r"(?<x>b)\1", "-e", r"(c)\k<1>"])
+        .write_stdin("aa\nbb\ncc\nab\n")
+        .assert()
+        .success()
+        .stdout("aa\nbb\ncc\n");
+    // Invalid bytes don't split lines in two for anchors and lookaround
+    Command::cargo_bin(PRG)?
+        .args(["-P", "-x", "foo"])
+        .write_stdin(b"foo\xffbar\n".as_slice())
+        .assert()
+        .code(1);
+    Command::cargo_bin(PRG)?
+        .args(["-P", "-o", r"^\w+|(?<=o.)\w+$|o.b"])
+        .write_stdin(b"foo\xffbar\n".as_slice())
+        .assert()
+        .success()
+        .stdout(b"foo\nbar\n".as_slice());
+    Command::cargo_bin(PRG)?
+        .args(["-P", "-o", "o.b"])
+        .write_stdin(b"foo\xffbar\n".as_slice())
+        .assert()
+        .success()
+        .stdout(b"o\xffb\n".as_slice());
+    // Lines end with NUL with -z, with or without -U
+    Command::cargo_bin(PRG)?
+        .args(["-P", "-z", "a.b$"])
+        .write_stdin("a\nb\0ab\0")
+        .assert()
+        .success()
+        .stdout("a\nb\0");
+    Command::cargo_bin(PRG)?
+        .args(["-P", "-z", "-U", "-o", "^b.c$"])
+        .write_stdin("a\0b\nc\0bc\nd\0")
+        .assert()
+        .success()
+        .stdout("b\nc\0");
+    // Runaway backtracking fails the file rather than hanging
+    Command::cargo_bin(PRG)?
+        .args(["-P", r"^(a|a)*(?<!a)b"])
+        .write_stdin(format!("b\n{}\n", "a".repeat(50)))
+        .assert()
+        .code(2)
+        .stdout("b\n")
+        .stderr("-: Pattern backtracked more than 1000000 times\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_pcre() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-P", "-e", "ok", "-e", r"(?<x>a)\k<y>", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(r#"Invalid pattern "(?<x>a)\k<y>""#));
+    Command::cargo_bin(PRG)?
+        .args(["-F", "-E", "foo", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}